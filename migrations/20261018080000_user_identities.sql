@@ -0,0 +1,13 @@
+-- Accounts signed into through single sign-on or a directory, keyed by the
+-- issuer and the subject it vouches for. Only these links let an external
+-- identity into an account; a matching email alone never does.
+CREATE TABLE user_identities (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (issuer, subject)
+);
+
+CREATE INDEX user_identities_user_id_idx ON user_identities (user_id);
@@ -0,0 +1,7 @@
+-- Create ENUM type for application roles
+CREATE TYPE user_role AS ENUM ('User', 'Admin');
+
+-- Add the role column to the users table. Accounts registered locally or
+-- through single sign-on own everything in them and are admins; directory
+-- accounts get their role from LDAP_GROUP_ROLES on every login.
+ALTER TABLE users ADD COLUMN role user_role NOT NULL DEFAULT 'Admin';
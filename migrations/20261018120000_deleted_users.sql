@@ -0,0 +1,8 @@
+-- Minimal audit record left behind when an account is deleted
+CREATE TABLE deleted_users (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL,
+    email_hash VARCHAR(64) NOT NULL,
+    secrets_dropped BOOLEAN NOT NULL DEFAULT FALSE,
+    deleted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,14 @@
+-- Bind client certificates to users for mutual-TLS authentication. A binding
+-- is only made from the certificate presented on the request's own TLS
+-- connection, which proves possession of the private key, and is matched by
+-- the certificate's SHA-256 fingerprint alone; the subject is informational.
+CREATE TABLE client_certificates (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    fingerprint VARCHAR(64) NOT NULL UNIQUE,
+    subject TEXT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX client_certificates_user_id_idx ON client_certificates (user_id);
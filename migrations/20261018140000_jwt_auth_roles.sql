@@ -0,0 +1,12 @@
+-- Let CI jobs and pods exchange platform-issued OIDC tokens for short-lived access tokens
+CREATE TABLE jwt_auth_roles (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    issuer TEXT NOT NULL,
+    audience TEXT NOT NULL,
+    bound_claims JSONB NOT NULL,
+    token_ttl_minutes INTEGER NOT NULL DEFAULT 15,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, name)
+);
@@ -0,0 +1,23 @@
+-- Machine roles owned by a user; machines log in with the role id and a secret id
+CREATE TABLE approles (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    allowed_secrets UUID[] NULL,
+    token_ttl_minutes INTEGER NOT NULL DEFAULT 15,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, name)
+);
+
+-- Only a SHA-256 hash of each secret id is kept
+CREATE TABLE approle_secret_ids (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    approle_id UUID NOT NULL REFERENCES approles(id) ON DELETE CASCADE,
+    secret_id_hash VARCHAR(64) NOT NULL UNIQUE,
+    uses_remaining INTEGER NULL,
+    bound_cidrs TEXT[] NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX approle_secret_ids_approle_id_idx ON approle_secret_ids (approle_id);
@@ -0,0 +1,27 @@
+-- Access level granted by an API key
+CREATE TYPE api_key_access AS ENUM ('Read', 'ReadWrite');
+
+-- Replace the single per-user API key with any number of named, scoped keys
+CREATE TABLE api_keys (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    key VARCHAR(255) NOT NULL UNIQUE,
+    access api_key_access NOT NULL DEFAULT 'Read',
+    allowed_secrets UUID[] NULL,
+    allowed_paths TEXT[] NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMP WITH TIME ZONE NULL,
+    revoked_at TIMESTAMP WITH TIME ZONE NULL
+);
+
+CREATE INDEX api_keys_user_id_idx ON api_keys (user_id);
+
+-- Existing keys keep working as read-only keys named `default`
+INSERT INTO api_keys (user_id, name, key, access)
+SELECT id, 'default', api_keys, 'Read'
+FROM users
+WHERE api_keys IS NOT NULL;
+
+ALTER TABLE users DROP COLUMN api_keys;
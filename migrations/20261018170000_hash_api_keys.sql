@@ -0,0 +1,12 @@
+-- API keys are stored as an HMAC-SHA256 hash plus a short prefix for lookup and display.
+-- Existing plaintext keys are hashed by the server on its next start, since the
+-- hash secret is not known to the database.
+ALTER TABLE api_keys ADD COLUMN key_prefix VARCHAR(16) NULL;
+ALTER TABLE api_keys ADD COLUMN key_hash VARCHAR(64) NULL UNIQUE;
+
+UPDATE api_keys SET key_prefix = LEFT(key, 12);
+
+ALTER TABLE api_keys ALTER COLUMN key_prefix SET NOT NULL;
+ALTER TABLE api_keys ALTER COLUMN key DROP NOT NULL;
+
+CREATE INDEX api_keys_key_prefix_idx ON api_keys (key_prefix);
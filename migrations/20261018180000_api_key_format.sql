@@ -0,0 +1,3 @@
+-- New keys look like `sbk_<environment>_<random>_<checksum>` and keep the
+-- environment plus 8 random characters as their prefix.
+ALTER TABLE api_keys ALTER COLUMN key_prefix TYPE VARCHAR(40);
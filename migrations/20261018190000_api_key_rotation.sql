@@ -0,0 +1,4 @@
+-- Rotating a key links it to its successor and shortens its expiry to the
+-- grace period, after which only the successor works.
+ALTER TABLE api_keys ADD COLUMN replaced_by UUID NULL REFERENCES api_keys(id) ON DELETE SET NULL;
+ALTER TABLE api_keys ADD COLUMN rotated_at TIMESTAMP WITH TIME ZONE NULL;
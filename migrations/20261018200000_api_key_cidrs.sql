@@ -0,0 +1,12 @@
+-- API keys can be limited to client address ranges; requests from anywhere
+-- else are rejected and recorded.
+ALTER TABLE api_keys ADD COLUMN allowed_cidrs TEXT[] NULL;
+
+CREATE TABLE api_key_rejections (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    api_key_id UUID NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    ip_address VARCHAR(45) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX api_key_rejections_api_key_id_idx ON api_key_rejections (api_key_id, created_at DESC);
@@ -0,0 +1,3 @@
+-- Usage is collected in memory and written in batches, see utils::api_key_usage.
+ALTER TABLE api_keys ADD COLUMN last_used_ip VARCHAR(45) NULL;
+ALTER TABLE api_keys ADD COLUMN request_count BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- Environments live in each user's own database, so there is no foreign key;
+-- a key whose environment was deleted simply finds no secrets.
+ALTER TABLE api_keys ADD COLUMN environment_id UUID NULL;
@@ -1,9 +1,78 @@
+use std::collections::HashMap;
+
+use axum_extra::extract::cookie::SameSite;
+
+use crate::{models::UserRole, utils::{api_key, cidr}};
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+    pub trust_email: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LdapConfig {
+    pub url: String,
+    pub bind_dn: Option<String>,
+    pub bind_password: Option<String>,
+    pub user_base_dn: String,
+    pub user_filter: String,
+    pub name_attribute: String,
+    pub group_attribute: String,
+    pub group_roles: Vec<(String, UserRole)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordHashingConfig {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub pepper: Option<String>,
+    pub pepper_id: String,
+    pub retired_peppers: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+    pub client_ca_path: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkloadIssuer {
+    pub issuer: String,
+    pub jwks_url: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CookieConfig {
+    pub domain: Option<String>,
+    pub secure: bool,
+    pub same_site: SameSite,
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
     pub jwt_secret: String,
+    pub api_key_secret: String,
+    pub api_key_environment: String,
+    pub api_key_rotation_grace_minutes: i64,
+    pub trusted_proxy_cidrs: Vec<String>,
     pub jwt_maxage: i64,
+    pub jwt_signing_keys_file: Option<String>,
     pub port: u16,
+    pub password_hashing: PasswordHashingConfig,
+    pub oidc: Option<OidcConfig>,
+    pub ldap: Option<LdapConfig>,
+    pub tls: Option<TlsConfig>,
+    pub cookie: CookieConfig,
+    pub workload_issuers: Vec<WorkloadIssuer>,
 }
 
 impl Config {
@@ -12,11 +81,188 @@ impl Config {
         let jwt_secret = std::env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY must be set");
         let jwt_maxage = std::env::var("JWT_MAXAGE").expect("JWT_MAXAGE must be set");
 
+        let password_hashing = PasswordHashingConfig {
+            memory_kib: std::env::var("ARGON2_MEMORY_KIB").map(|v| v.parse().unwrap()).unwrap_or(19 * 1024),
+            iterations: std::env::var("ARGON2_ITERATIONS").map(|v| v.parse().unwrap()).unwrap_or(2),
+            parallelism: std::env::var("ARGON2_PARALLELISM").map(|v| v.parse().unwrap()).unwrap_or(1),
+            pepper: std::env::var("PASSWORD_PEPPER").ok(),
+            pepper_id: std::env::var("PASSWORD_PEPPER_ID").unwrap_or_else(|_| "pepper1".to_string()),
+            retired_peppers: parse_retired_peppers(&std::env::var("PASSWORD_RETIRED_PEPPERS").unwrap_or_default()),
+        };
+
+        let oidc = std::env::var("OIDC_ISSUER_URL").ok().map(|issuer_url| OidcConfig {
+            issuer_url: issuer_url.trim_end_matches('/').to_string(),
+            client_id: std::env::var("OIDC_CLIENT_ID").expect("OIDC_CLIENT_ID must be set"),
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok(),
+            redirect_url: std::env::var("OIDC_REDIRECT_URL").expect("OIDC_REDIRECT_URL must be set"),
+            scopes: std::env::var("OIDC_SCOPES").unwrap_or_else(|_| "openid email profile".to_string()),
+            trust_email: std::env::var("OIDC_TRUST_EMAIL").map(|v| v.parse().unwrap()).unwrap_or(false),
+        });
+
+        let ldap = std::env::var("LDAP_URL").ok().map(|url| LdapConfig {
+            url,
+            bind_dn: std::env::var("LDAP_BIND_DN").ok(),
+            bind_password: std::env::var("LDAP_BIND_PASSWORD").ok(),
+            user_base_dn: std::env::var("LDAP_USER_BASE_DN").expect("LDAP_USER_BASE_DN must be set"),
+            user_filter: std::env::var("LDAP_USER_FILTER").unwrap_or_else(|_| "(mail={email})".to_string()),
+            name_attribute: std::env::var("LDAP_NAME_ATTRIBUTE").unwrap_or_else(|_| "cn".to_string()),
+            group_attribute: std::env::var("LDAP_GROUP_ATTRIBUTE").unwrap_or_else(|_| "memberOf".to_string()),
+            group_roles: parse_group_roles(&std::env::var("LDAP_GROUP_ROLES").unwrap_or_default()),
+        });
+
+        let tls = std::env::var("TLS_CERT_PATH").ok().map(|cert_path| TlsConfig {
+            cert_path,
+            key_path: std::env::var("TLS_KEY_PATH").expect("TLS_KEY_PATH must be set"),
+            client_ca_path: std::env::var("TLS_CLIENT_CA_PATH").ok(),
+        });
+
+        // Cookies default to `Secure` whenever the server terminates TLS itself;
+        // deployments behind a TLS proxy should set COOKIE_SECURE=true.
+        let cookie = CookieConfig {
+            domain: std::env::var("COOKIE_DOMAIN").ok(),
+            secure: std::env::var("COOKIE_SECURE").map(|v| v.parse().unwrap()).unwrap_or(tls.is_some()),
+            same_site: parse_same_site(&std::env::var("COOKIE_SAME_SITE").unwrap_or_else(|_| "Lax".to_string())),
+        };
+
+        if cookie.same_site == SameSite::None && !cookie.secure {
+            panic!("COOKIE_SAME_SITE=None requires COOKIE_SECURE=true");
+        }
+
+        let api_key_environment = std::env::var("API_KEY_ENVIRONMENT").unwrap_or_else(|_| "live".to_string());
+
+        if !api_key::is_valid_environment(&api_key_environment) {
+            panic!("API_KEY_ENVIRONMENT must be 1 to 16 lowercase letters or digits");
+        }
+
         Config {
             database_url,
+            api_key_secret: std::env::var("API_KEY_HASH_SECRET").unwrap_or_else(|_| jwt_secret.clone()),
+            api_key_environment,
+            trusted_proxy_cidrs: parse_cidrs(&std::env::var("TRUSTED_PROXY_CIDRS").unwrap_or_default()),
+            api_key_rotation_grace_minutes: std::env::var("API_KEY_ROTATION_GRACE_MINUTES").map(|v| v.parse().unwrap()).unwrap_or(24 * 60),
             jwt_secret,
             jwt_maxage: jwt_maxage.parse::<i64>().unwrap(),
+            jwt_signing_keys_file: std::env::var("JWT_SIGNING_KEYS_FILE").ok(),
             port: 8000,
+            password_hashing,
+            oidc,
+            ldap,
+            tls,
+            cookie,
+            workload_issuers: parse_workload_issuers(&std::env::var("JWT_AUTH_ISSUERS").unwrap_or_default()),
         }
     }
-}
\ No newline at end of file
+}
+
+// Parses `group dn=>Role` pairs separated by `;`, e.g.
+// `cn=admins,ou=groups,dc=example,dc=org=>Admin`.
+fn parse_group_roles(value: &str) -> Vec<(String, UserRole)> {
+    value
+        .split(';')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (group, role) = pair
+                .split_once("=>")
+                .expect("LDAP_GROUP_ROLES entries must look like `group dn=>Role`");
+
+            let role = match role.trim() {
+                "Admin" => UserRole::Admin,
+                "User" => UserRole::User,
+                other => panic!("Unknown role `{}` in LDAP_GROUP_ROLES", other),
+            };
+
+            (group.trim().to_lowercase(), role)
+        })
+        .collect()
+}
+// Parses `pepper id=>pepper` pairs separated by `;`, e.g.
+// `pepper1=>my_old_pepper`. Hashes made under a retired pepper keep verifying
+// and are rehashed with the current one on the next login.
+fn parse_retired_peppers(value: &str) -> HashMap<String, String> {
+    value
+        .split(';')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            let (pepper_id, pepper) = pair
+                .split_once("=>")
+                .expect("PASSWORD_RETIRED_PEPPERS entries must look like `pepper id=>pepper`");
+
+            (pepper_id.trim().to_string(), pepper.to_string())
+        })
+        .collect()
+}
+
+// Parses issuers separated by `;`, each optionally followed by `=>jwks url`
+// for platforms that do not publish an OpenID discovery document, e.g.
+// `https://token.actions.githubusercontent.com;https://kubernetes.default.svc=>https://10.0.0.1/openid/v1/jwks`.
+fn parse_workload_issuers(value: &str) -> Vec<WorkloadIssuer> {
+    value
+        .split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| match entry.split_once("=>") {
+            Some((issuer, jwks_url)) => WorkloadIssuer {
+                issuer: issuer.trim().to_string(),
+                jwks_url: Some(jwks_url.trim().to_string()),
+            },
+            None => WorkloadIssuer {
+                issuer: entry.trim().to_string(),
+                jwks_url: None,
+            },
+        })
+        .collect()
+}
+
+// Parses comma-separated CIDR ranges or single addresses, e.g.
+// `10.0.0.0/8,192.168.1.10`.
+fn parse_cidrs(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|entry| entry.trim())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match cidr::parse(entry) {
+            Some(_) => entry.to_string(),
+            None => panic!("Invalid CIDR `{}` in TRUSTED_PROXY_CIDRS", entry),
+        })
+        .collect()
+}
+
+fn parse_same_site(value: &str) -> SameSite {
+    match value.to_lowercase().as_str() {
+        "strict" => SameSite::Strict,
+        "lax" => SameSite::Lax,
+        "none" => SameSite::None,
+        other => panic!("Unknown COOKIE_SAME_SITE `{}`, use Strict, Lax or None", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_group_roles() {
+        let roles = parse_group_roles(" CN=Admins,OU=Groups,DC=example,DC=org => Admin ;cn=staff,ou=groups,dc=example,dc=org=>User;");
+
+        assert_eq!(roles, vec![
+            ("cn=admins,ou=groups,dc=example,dc=org".to_string(), UserRole::Admin),
+            ("cn=staff,ou=groups,dc=example,dc=org".to_string(), UserRole::User),
+        ]);
+    }
+
+    #[test]
+    fn parses_empty_group_roles() {
+        assert!(parse_group_roles("").is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown role `Owner` in LDAP_GROUP_ROLES")]
+    fn rejects_unknown_roles() {
+        parse_group_roles("cn=admins,dc=example,dc=org=>Owner");
+    }
+
+    #[test]
+    #[should_panic(expected = "LDAP_GROUP_ROLES entries must look like `group dn=>Role`")]
+    fn rejects_entries_without_a_role() {
+        parse_group_roles("cn=admins,dc=example,dc=org");
+    }
+}
@@ -1,8 +1,11 @@
 use sqlx::{Postgres, Pool, types::Json};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use uuid::Uuid;
 
-use crate::models::{DbConnection, EncryptionMethod, User};
+use std::collections::HashMap;
+
+use crate::models::{ApiKey, ApiKeyAccess, ApiKeyRejection, AppRole, AppRoleSecretId, ClientCertificate, DbConnection, EncryptionMethod, JwtAuthRole, Session, User, UserRole};
 
 #[derive(Debug, Clone)]
 pub struct DBClient {
@@ -22,7 +25,6 @@ pub trait UserExt {
         user_id: Option<Uuid>,
         name: Option<&str>,
         email: Option<&str>,
-        api_key: Option<&str>,
     ) -> Result<Option<User>, sqlx::Error>;
 
     async fn save_user<T: Into<String> + Send>(
@@ -30,7 +32,6 @@ pub trait UserExt {
         name: T,
         email: T,
         password: T,
-        api_key: T,
     ) -> Result<User, sqlx::Error>;
 
     async fn update_user_name<T: Into<String> + Send>(
@@ -45,6 +46,32 @@ pub trait UserExt {
         new_password: String,
     ) -> Result<User, sqlx::Error>;
 
+    async fn update_user_role(
+        &self,
+        user_id: Uuid,
+        role: UserRole,
+    ) -> Result<User, sqlx::Error>;
+
+    async fn get_password_history(
+        &self,
+        user_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<String>, sqlx::Error>;
+
+    async fn save_password_history(
+        &self,
+        user_id: Uuid,
+        password: String,
+        keep: i64,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn delete_user(
+        &self,
+        user_id: Uuid,
+        email_hash: String,
+        secrets_dropped: bool,
+    ) -> Result<(), sqlx::Error>;
+
     async fn save_database_details(
         &self,
         user_id: Uuid,
@@ -66,7 +93,6 @@ impl UserExt for DBClient {
         user_id: Option<Uuid>,
         name: Option<&str>,
         email: Option<&str>,
-        api_key: Option<&str>,
     ) -> Result<Option<User>, sqlx::Error> {
    
         let query = r#"
@@ -77,16 +103,15 @@ impl UserExt for DBClient {
                 password, 
                 encryption_method, 
                 keys, 
-                api_keys, 
                 db_connection,  
+                role, 
                 created_at, 
                 updated_at 
             FROM users 
             WHERE 
                 ($1::uuid IS NULL OR id = $1) AND
                 ($2::text IS NULL OR name = $2) AND
-                ($3::text IS NULL OR email = $3) AND
-                ($4::text IS NULL OR api_keys = $4)
+                ($3::text IS NULL OR email = $3)
         "#;
 
     
@@ -94,7 +119,6 @@ impl UserExt for DBClient {
             .bind(user_id)
             .bind(name)
             .bind(email)
-            .bind(api_key)
             .fetch_optional(&self.pool)
             .await?;
     
@@ -106,20 +130,18 @@ impl UserExt for DBClient {
         &self,
         name: T,
         email: T,
-        password: T,
-        api_key: T
+        password: T
     ) -> Result<User, sqlx::Error> {
         let user = sqlx::query_as!(
             User,
             r#"
-            INSERT INTO users (name, email, password, api_keys) 
-            VALUES ($1, $2, $3, $4) 
-            RETURNING id, name, email, password, encryption_method as "encryption_method: EncryptionMethod", keys, api_keys, db_connection as "db_connection: Json<DbConnection>", created_at, updated_at
+            INSERT INTO users (name, email, password) 
+            VALUES ($1, $2, $3) 
+            RETURNING id, name, email, password, encryption_method as "encryption_method: EncryptionMethod", keys, db_connection as "db_connection: Json<DbConnection>", role as "role: UserRole", created_at, updated_at
             "#,
             name.into(),
             email.into(),
             password.into(),
-            api_key.into(),
         ).fetch_one(&self.pool)
         .await?;
 
@@ -137,7 +159,7 @@ impl UserExt for DBClient {
             UPDATE users
             SET name = $1, updated_at = Now()
             WHERE id = $2
-            RETURNING id, name, email, password,encryption_method as "encryption_method: EncryptionMethod", keys, api_keys, db_connection as "db_connection: Json<DbConnection>", created_at, updated_at
+            RETURNING id, name, email, password,encryption_method as "encryption_method: EncryptionMethod", keys, db_connection as "db_connection: Json<DbConnection>", role as "role: UserRole", created_at, updated_at
             "#,
             new_name.into(),
             user_id
@@ -158,7 +180,7 @@ impl UserExt for DBClient {
             UPDATE users
             SET password = $1, updated_at = Now()
             WHERE id = $2
-            RETURNING id, name, email, password, encryption_method as "encryption_method: EncryptionMethod", keys, api_keys, db_connection as "db_connection: Json<DbConnection>", created_at, updated_at
+            RETURNING id, name, email, password, encryption_method as "encryption_method: EncryptionMethod", keys, db_connection as "db_connection: Json<DbConnection>", role as "role: UserRole", created_at, updated_at
             "#,
             new_password,
             user_id
@@ -168,6 +190,148 @@ impl UserExt for DBClient {
         Ok(user)
     }
 
+    async fn update_user_role(
+        &self,
+        user_id: Uuid,
+        role: UserRole
+    ) -> Result<User, sqlx::Error> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            UPDATE users
+            SET role = $1, updated_at = Now()
+            WHERE id = $2
+            RETURNING id, name, email, password, encryption_method as "encryption_method: EncryptionMethod", keys, db_connection as "db_connection: Json<DbConnection>", role as "role: UserRole", created_at, updated_at
+            "#,
+            role as UserRole,
+            user_id
+        ).fetch_one(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn get_password_history(
+        &self,
+        user_id: Uuid,
+        limit: i64
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let passwords = sqlx::query_scalar!(
+            r#"
+            SELECT password
+            FROM password_history
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit
+        ).fetch_all(&self.pool)
+        .await?;
+
+        Ok(passwords)
+    }
+
+    async fn save_password_history(
+        &self,
+        user_id: Uuid,
+        password: String,
+        keep: i64
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO password_history (user_id, password)
+            VALUES ($1, $2)
+            "#,
+            user_id,
+            password
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM password_history
+            WHERE user_id = $1 AND id NOT IN (
+                SELECT id FROM password_history
+                WHERE user_id = $1
+                ORDER BY created_at DESC
+                LIMIT $2
+            )
+            "#,
+            user_id,
+            keep
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_user(
+        &self,
+        user_id: Uuid,
+        email_hash: String,
+        secrets_dropped: bool
+    ) -> Result<(), sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM api_keys
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND revoked_at IS NULL
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO deleted_users (user_id, email_hash, secrets_dropped)
+            VALUES ($1, $2, $3)
+            "#,
+            user_id,
+            email_hash,
+            secrets_dropped
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        // Deleting the row takes the encryption key with it. Postgres keeps the
+        // old row version until it is vacuumed, and backups and WAL archives
+        // keep theirs until they expire, so the key is gone from the live
+        // database only.
+        sqlx::query!(
+            r#"
+            DELETE FROM users
+            WHERE id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
     async fn save_database_details(
         &self,
         user_id: Uuid,
@@ -212,4 +376,1213 @@ impl UserExt for DBClient {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+pub trait IdentityExt {
+    async fn get_user_by_identity(
+        &self,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<Option<User>, sqlx::Error>;
+
+    async fn save_identity(
+        &self,
+        user_id: Uuid,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn has_identity(
+        &self,
+        user_id: Uuid,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn save_user_with_identity(
+        &self,
+        name: &str,
+        email: &str,
+        password: &str,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<User, sqlx::Error>;
+}
+
+#[async_trait]
+impl IdentityExt for DBClient {
+    async fn get_user_by_identity(
+        &self,
+        issuer: &str,
+        subject: &str
+    ) -> Result<Option<User>, sqlx::Error> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            SELECT u.id, u.name, u.email, u.password, u.encryption_method as "encryption_method: EncryptionMethod", u.keys, u.db_connection as "db_connection: Json<DbConnection>", u.role as "role: UserRole", u.created_at, u.updated_at
+            FROM users u
+            JOIN user_identities i ON i.user_id = u.id
+            WHERE i.issuer = $1 AND i.subject = $2
+            "#,
+            issuer,
+            subject
+        ).fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    async fn save_identity(
+        &self,
+        user_id: Uuid,
+        issuer: &str,
+        subject: &str
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO user_identities (user_id, issuer, subject)
+            VALUES ($1, $2, $3)
+            "#,
+            user_id,
+            issuer,
+            subject
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn has_identity(
+        &self,
+        user_id: Uuid
+    ) -> Result<bool, sqlx::Error> {
+        let exists = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM user_identities WHERE user_id = $1) AS "exists!"
+            "#,
+            user_id
+        ).fetch_one(&self.pool)
+        .await?;
+
+        Ok(exists)
+    }
+
+    // Provisions an account for an external identity that has none yet. The
+    // account and its link are created together, so a failed link never
+    // leaves an orphaned account behind.
+    async fn save_user_with_identity(
+        &self,
+        name: &str,
+        email: &str,
+        password: &str,
+        issuer: &str,
+        subject: &str
+    ) -> Result<User, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            INSERT INTO users (name, email, password) 
+            VALUES ($1, $2, $3) 
+            RETURNING id, name, email, password, encryption_method as "encryption_method: EncryptionMethod", keys, db_connection as "db_connection: Json<DbConnection>", role as "role: UserRole", created_at, updated_at
+            "#,
+            name,
+            email,
+            password
+        ).fetch_one(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO user_identities (user_id, issuer, subject)
+            VALUES ($1, $2, $3)
+            "#,
+            user.id,
+            issuer,
+            subject
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(user)
+    }
+}
+
+#[async_trait]
+pub trait SessionExt {
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        ip_address: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<Session, sqlx::Error>;
+
+    async fn get_session(
+        &self,
+        session_id: Uuid,
+    ) -> Result<Option<Session>, sqlx::Error>;
+
+    async fn get_user_sessions(
+        &self,
+        user_id: Uuid,
+        max_age_minutes: i64,
+    ) -> Result<Vec<Session>, sqlx::Error>;
+
+    async fn touch_session(
+        &self,
+        session_id: Uuid,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn revoke_session(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+    ) -> Result<bool, sqlx::Error>;
+}
+
+#[async_trait]
+impl SessionExt for DBClient {
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        ip_address: Option<String>,
+        user_agent: Option<String>
+    ) -> Result<Session, sqlx::Error> {
+        let session = sqlx::query_as!(
+            Session,
+            r#"
+            INSERT INTO sessions (user_id, ip_address, user_agent)
+            VALUES ($1, $2, $3)
+            RETURNING id, user_id, ip_address, user_agent, created_at, last_seen_at, revoked_at
+            "#,
+            user_id,
+            ip_address,
+            user_agent
+        ).fetch_one(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn get_session(
+        &self,
+        session_id: Uuid
+    ) -> Result<Option<Session>, sqlx::Error> {
+        let session = sqlx::query_as!(
+            Session,
+            r#"
+            SELECT id, user_id, ip_address, user_agent, created_at, last_seen_at, revoked_at
+            FROM sessions
+            WHERE id = $1
+            "#,
+            session_id
+        ).fetch_optional(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    async fn get_user_sessions(
+        &self,
+        user_id: Uuid,
+        max_age_minutes: i64
+    ) -> Result<Vec<Session>, sqlx::Error> {
+        let sessions = sqlx::query_as!(
+            Session,
+            r#"
+            SELECT id, user_id, ip_address, user_agent, created_at, last_seen_at, revoked_at
+            FROM sessions
+            WHERE user_id = $1
+                AND revoked_at IS NULL
+                AND created_at > NOW() - make_interval(mins => $2::int)
+            ORDER BY last_seen_at DESC
+            "#,
+            user_id,
+            max_age_minutes as i32
+        ).fetch_all(&self.pool)
+        .await?;
+
+        Ok(sessions)
+    }
+
+    async fn touch_session(
+        &self,
+        session_id: Uuid
+    ) -> Result<(), sqlx::Error> {
+        // Only write when the timestamp is stale, so busy clients do not
+        // turn every request into an UPDATE.
+        sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET last_seen_at = NOW()
+            WHERE id = $1 AND last_seen_at < NOW() - INTERVAL '1 minute'
+            "#,
+            session_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_session(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE sessions
+            SET revoked_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+            session_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+pub trait ClientCertificateExt {
+    async fn save_client_certificate(
+        &self,
+        user_id: Uuid,
+        name: String,
+        fingerprint: String,
+        subject: Option<String>,
+    ) -> Result<ClientCertificate, sqlx::Error>;
+
+    async fn get_client_certificates(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<ClientCertificate>, sqlx::Error>;
+
+    async fn delete_client_certificate(
+        &self,
+        user_id: Uuid,
+        certificate_id: Uuid,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn get_user_by_client_certificate(
+        &self,
+        fingerprint: &str,
+    ) -> Result<Option<User>, sqlx::Error>;
+}
+
+#[async_trait]
+impl ClientCertificateExt for DBClient {
+    async fn save_client_certificate(
+        &self,
+        user_id: Uuid,
+        name: String,
+        fingerprint: String,
+        subject: Option<String>
+    ) -> Result<ClientCertificate, sqlx::Error> {
+        let certificate = sqlx::query_as!(
+            ClientCertificate,
+            r#"
+            INSERT INTO client_certificates (user_id, name, fingerprint, subject)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, name, fingerprint, subject, created_at
+            "#,
+            user_id,
+            name,
+            fingerprint,
+            subject
+        ).fetch_one(&self.pool)
+        .await?;
+
+        Ok(certificate)
+    }
+
+    async fn get_client_certificates(
+        &self,
+        user_id: Uuid
+    ) -> Result<Vec<ClientCertificate>, sqlx::Error> {
+        let certificates = sqlx::query_as!(
+            ClientCertificate,
+            r#"
+            SELECT id, user_id, name, fingerprint, subject, created_at
+            FROM client_certificates
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        ).fetch_all(&self.pool)
+        .await?;
+
+        Ok(certificates)
+    }
+
+    async fn delete_client_certificate(
+        &self,
+        user_id: Uuid,
+        certificate_id: Uuid
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM client_certificates
+            WHERE id = $1 AND user_id = $2
+            "#,
+            certificate_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Only the fingerprint identifies a certificate; any CA-issued certificate
+    // could carry a given subject.
+    async fn get_user_by_client_certificate(
+        &self,
+        fingerprint: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            SELECT u.id, u.name, u.email, u.password, u.encryption_method as "encryption_method: EncryptionMethod", u.keys, u.db_connection as "db_connection: Json<DbConnection>", u.role as "role: UserRole", u.created_at, u.updated_at
+            FROM client_certificates c
+            JOIN users u ON u.id = c.user_id
+            WHERE c.fingerprint = $1
+            "#,
+            fingerprint
+        ).fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+}
+
+#[async_trait]
+pub trait JwtAuthRoleExt {
+    async fn save_jwt_auth_role(
+        &self,
+        user_id: Uuid,
+        name: String,
+        issuer: String,
+        audience: String,
+        bound_claims: HashMap<String, Vec<String>>,
+        token_ttl_minutes: i32,
+    ) -> Result<JwtAuthRole, sqlx::Error>;
+
+    async fn get_jwt_auth_roles(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<JwtAuthRole>, sqlx::Error>;
+
+    async fn get_jwt_auth_role(
+        &self,
+        role_id: Uuid,
+    ) -> Result<Option<JwtAuthRole>, sqlx::Error>;
+
+    async fn delete_jwt_auth_role(
+        &self,
+        user_id: Uuid,
+        role_id: Uuid,
+    ) -> Result<bool, sqlx::Error>;
+}
+
+#[async_trait]
+impl JwtAuthRoleExt for DBClient {
+    async fn save_jwt_auth_role(
+        &self,
+        user_id: Uuid,
+        name: String,
+        issuer: String,
+        audience: String,
+        bound_claims: HashMap<String, Vec<String>>,
+        token_ttl_minutes: i32
+    ) -> Result<JwtAuthRole, sqlx::Error> {
+        let role = sqlx::query_as!(
+            JwtAuthRole,
+            r#"
+            INSERT INTO jwt_auth_roles (user_id, name, issuer, audience, bound_claims, token_ttl_minutes)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, user_id, name, issuer, audience, bound_claims as "bound_claims: Json<HashMap<String, Vec<String>>>", token_ttl_minutes, created_at
+            "#,
+            user_id,
+            name,
+            issuer,
+            audience,
+            Json(bound_claims) as _,
+            token_ttl_minutes
+        ).fetch_one(&self.pool)
+        .await?;
+
+        Ok(role)
+    }
+
+    async fn get_jwt_auth_roles(
+        &self,
+        user_id: Uuid
+    ) -> Result<Vec<JwtAuthRole>, sqlx::Error> {
+        let roles = sqlx::query_as!(
+            JwtAuthRole,
+            r#"
+            SELECT id, user_id, name, issuer, audience, bound_claims as "bound_claims: Json<HashMap<String, Vec<String>>>", token_ttl_minutes, created_at
+            FROM jwt_auth_roles
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        ).fetch_all(&self.pool)
+        .await?;
+
+        Ok(roles)
+    }
+
+    async fn get_jwt_auth_role(
+        &self,
+        role_id: Uuid
+    ) -> Result<Option<JwtAuthRole>, sqlx::Error> {
+        let role = sqlx::query_as!(
+            JwtAuthRole,
+            r#"
+            SELECT id, user_id, name, issuer, audience, bound_claims as "bound_claims: Json<HashMap<String, Vec<String>>>", token_ttl_minutes, created_at
+            FROM jwt_auth_roles
+            WHERE id = $1
+            "#,
+            role_id
+        ).fetch_optional(&self.pool)
+        .await?;
+
+        Ok(role)
+    }
+
+    async fn delete_jwt_auth_role(
+        &self,
+        user_id: Uuid,
+        role_id: Uuid
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM jwt_auth_roles
+            WHERE id = $1 AND user_id = $2
+            "#,
+            role_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+pub trait AppRoleExt {
+    async fn save_approle(
+        &self,
+        user_id: Uuid,
+        name: String,
+        allowed_secrets: Option<Vec<Uuid>>,
+        token_ttl_minutes: i32,
+    ) -> Result<AppRole, sqlx::Error>;
+
+    async fn get_approles(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<AppRole>, sqlx::Error>;
+
+    async fn get_approle(
+        &self,
+        approle_id: Uuid,
+    ) -> Result<Option<AppRole>, sqlx::Error>;
+
+    async fn delete_approle(
+        &self,
+        user_id: Uuid,
+        approle_id: Uuid,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn save_approle_secret_id(
+        &self,
+        approle_id: Uuid,
+        secret_id_hash: String,
+        uses_remaining: Option<i32>,
+        bound_cidrs: Option<Vec<String>>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<AppRoleSecretId, sqlx::Error>;
+
+    async fn get_approle_secret_ids(
+        &self,
+        approle_id: Uuid,
+    ) -> Result<Vec<AppRoleSecretId>, sqlx::Error>;
+
+    async fn get_approle_secret_id(
+        &self,
+        approle_id: Uuid,
+        secret_id_hash: &str,
+    ) -> Result<Option<AppRoleSecretId>, sqlx::Error>;
+
+    async fn consume_approle_secret_id(
+        &self,
+        secret_id: Uuid,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn delete_approle_secret_id(
+        &self,
+        approle_id: Uuid,
+        secret_id: Uuid,
+    ) -> Result<bool, sqlx::Error>;
+}
+
+#[async_trait]
+impl AppRoleExt for DBClient {
+    async fn save_approle(
+        &self,
+        user_id: Uuid,
+        name: String,
+        allowed_secrets: Option<Vec<Uuid>>,
+        token_ttl_minutes: i32
+    ) -> Result<AppRole, sqlx::Error> {
+        let approle = sqlx::query_as!(
+            AppRole,
+            r#"
+            INSERT INTO approles (user_id, name, allowed_secrets, token_ttl_minutes)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, name, allowed_secrets, token_ttl_minutes, created_at
+            "#,
+            user_id,
+            name,
+            allowed_secrets.as_deref(),
+            token_ttl_minutes
+        ).fetch_one(&self.pool)
+        .await?;
+
+        Ok(approle)
+    }
+
+    async fn get_approles(
+        &self,
+        user_id: Uuid
+    ) -> Result<Vec<AppRole>, sqlx::Error> {
+        let approles = sqlx::query_as!(
+            AppRole,
+            r#"
+            SELECT id, user_id, name, allowed_secrets, token_ttl_minutes, created_at
+            FROM approles
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        ).fetch_all(&self.pool)
+        .await?;
+
+        Ok(approles)
+    }
+
+    async fn get_approle(
+        &self,
+        approle_id: Uuid
+    ) -> Result<Option<AppRole>, sqlx::Error> {
+        let approle = sqlx::query_as!(
+            AppRole,
+            r#"
+            SELECT id, user_id, name, allowed_secrets, token_ttl_minutes, created_at
+            FROM approles
+            WHERE id = $1
+            "#,
+            approle_id
+        ).fetch_optional(&self.pool)
+        .await?;
+
+        Ok(approle)
+    }
+
+    async fn delete_approle(
+        &self,
+        user_id: Uuid,
+        approle_id: Uuid
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM approles
+            WHERE id = $1 AND user_id = $2
+            "#,
+            approle_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn save_approle_secret_id(
+        &self,
+        approle_id: Uuid,
+        secret_id_hash: String,
+        uses_remaining: Option<i32>,
+        bound_cidrs: Option<Vec<String>>,
+        expires_at: Option<DateTime<Utc>>
+    ) -> Result<AppRoleSecretId, sqlx::Error> {
+        let secret_id = sqlx::query_as!(
+            AppRoleSecretId,
+            r#"
+            INSERT INTO approle_secret_ids (approle_id, secret_id_hash, uses_remaining, bound_cidrs, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, approle_id, uses_remaining, bound_cidrs, expires_at, created_at
+            "#,
+            approle_id,
+            secret_id_hash,
+            uses_remaining,
+            bound_cidrs.as_deref(),
+            expires_at
+        ).fetch_one(&self.pool)
+        .await?;
+
+        Ok(secret_id)
+    }
+
+    async fn get_approle_secret_ids(
+        &self,
+        approle_id: Uuid
+    ) -> Result<Vec<AppRoleSecretId>, sqlx::Error> {
+        let secret_ids = sqlx::query_as!(
+            AppRoleSecretId,
+            r#"
+            SELECT id, approle_id, uses_remaining, bound_cidrs, expires_at, created_at
+            FROM approle_secret_ids
+            WHERE approle_id = $1
+            ORDER BY created_at DESC
+            "#,
+            approle_id
+        ).fetch_all(&self.pool)
+        .await?;
+
+        Ok(secret_ids)
+    }
+
+    async fn get_approle_secret_id(
+        &self,
+        approle_id: Uuid,
+        secret_id_hash: &str
+    ) -> Result<Option<AppRoleSecretId>, sqlx::Error> {
+        let secret_id = sqlx::query_as!(
+            AppRoleSecretId,
+            r#"
+            SELECT id, approle_id, uses_remaining, bound_cidrs, expires_at, created_at
+            FROM approle_secret_ids
+            WHERE approle_id = $1 AND secret_id_hash = $2
+                AND (expires_at IS NULL OR expires_at > NOW())
+                AND (uses_remaining IS NULL OR uses_remaining > 0)
+            "#,
+            approle_id,
+            secret_id_hash
+        ).fetch_optional(&self.pool)
+        .await?;
+
+        Ok(secret_id)
+    }
+
+    async fn consume_approle_secret_id(
+        &self,
+        secret_id: Uuid
+    ) -> Result<bool, sqlx::Error> {
+        // Decrementing under the same conditions as the lookup means concurrent
+        // logins cannot use a secret id more often than allowed.
+        let result = sqlx::query!(
+            r#"
+            UPDATE approle_secret_ids
+            SET uses_remaining = uses_remaining - 1
+            WHERE id = $1
+                AND (expires_at IS NULL OR expires_at > NOW())
+                AND (uses_remaining IS NULL OR uses_remaining > 0)
+            "#,
+            secret_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_approle_secret_id(
+        &self,
+        approle_id: Uuid,
+        secret_id: Uuid
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM approle_secret_ids
+            WHERE id = $1 AND approle_id = $2
+            "#,
+            secret_id,
+            approle_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKeySettings {
+    pub name: String,
+    pub access: ApiKeyAccess,
+    pub allowed_secrets: Option<Vec<Uuid>>,
+    pub allowed_paths: Option<Vec<String>>,
+    pub allowed_cidrs: Option<Vec<String>>,
+    pub environment_id: Option<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+// Requests made with one key since the last flush of the usage tracker.
+#[derive(Debug, Clone)]
+pub struct ApiKeyUsage {
+    pub api_key_id: Uuid,
+    pub request_count: i64,
+    pub last_used_at: DateTime<Utc>,
+    pub last_used_ip: String,
+}
+
+// A client address turned away for one key since the last flush of the usage
+// tracker. Repeated attempts from the same address are merged into one.
+#[derive(Debug, Clone)]
+pub struct ApiKeyRejectedAddress {
+    pub api_key_id: Uuid,
+    pub ip_address: String,
+    pub rejected_at: DateTime<Utc>,
+}
+
+#[async_trait]
+pub trait ApiKeyExt {
+    async fn save_api_key(
+        &self,
+        user_id: Uuid,
+        key_prefix: String,
+        key_hash: String,
+        settings: ApiKeySettings,
+    ) -> Result<ApiKey, sqlx::Error>;
+
+    async fn get_api_keys(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<ApiKey>, sqlx::Error>;
+
+    async fn get_active_api_key(
+        &self,
+        key_prefix: &str,
+        key_hash: &str,
+    ) -> Result<Option<ApiKey>, sqlx::Error>;
+
+    async fn get_plaintext_api_keys(
+        &self,
+    ) -> Result<Vec<(Uuid, String)>, sqlx::Error>;
+
+    async fn set_api_key_hash(
+        &self,
+        api_key_id: Uuid,
+        key_hash: String,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn update_api_key(
+        &self,
+        user_id: Uuid,
+        api_key_id: Uuid,
+        settings: ApiKeySettings,
+    ) -> Result<Option<ApiKey>, sqlx::Error>;
+
+    async fn revoke_api_key(
+        &self,
+        user_id: Uuid,
+        api_key_id: Uuid,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn record_api_key_usage(
+        &self,
+        usages: &[ApiKeyUsage],
+    ) -> Result<(), sqlx::Error>;
+
+    async fn get_stale_api_keys(
+        &self,
+        user_id: Uuid,
+        unused_since: DateTime<Utc>,
+    ) -> Result<Vec<ApiKey>, sqlx::Error>;
+
+    async fn rotate_api_key(
+        &self,
+        user_id: Uuid,
+        api_key_id: Uuid,
+        key_prefix: String,
+        key_hash: String,
+        grace_until: DateTime<Utc>,
+    ) -> Result<Option<(ApiKey, ApiKey)>, sqlx::Error>;
+
+    async fn record_api_key_rejections(
+        &self,
+        rejections: &[ApiKeyRejectedAddress],
+        kept_per_key: i64,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn get_api_key_rejections(
+        &self,
+        api_key_id: Uuid,
+        limit: i64,
+    ) -> Result<Vec<ApiKeyRejection>, sqlx::Error>;
+}
+
+#[async_trait]
+impl ApiKeyExt for DBClient {
+    async fn save_api_key(
+        &self,
+        user_id: Uuid,
+        key_prefix: String,
+        key_hash: String,
+        settings: ApiKeySettings
+    ) -> Result<ApiKey, sqlx::Error> {
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            INSERT INTO api_keys (user_id, name, key_prefix, key_hash, access, allowed_secrets, allowed_paths, allowed_cidrs, environment_id, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING id, user_id, name, key_prefix, access as "access: ApiKeyAccess", allowed_secrets, allowed_paths, expires_at, created_at, last_used_at, revoked_at, replaced_by, rotated_at, allowed_cidrs, last_used_ip, request_count, environment_id
+            "#,
+            user_id,
+            settings.name,
+            key_prefix,
+            key_hash,
+            settings.access as ApiKeyAccess,
+            settings.allowed_secrets.as_deref(),
+            settings.allowed_paths.as_deref(),
+            settings.allowed_cidrs.as_deref(),
+            settings.environment_id,
+            settings.expires_at
+        ).fetch_one(&self.pool)
+        .await?;
+
+        Ok(api_key)
+    }
+
+    async fn get_api_keys(
+        &self,
+        user_id: Uuid
+    ) -> Result<Vec<ApiKey>, sqlx::Error> {
+        let api_keys = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, user_id, name, key_prefix, access as "access: ApiKeyAccess", allowed_secrets, allowed_paths, expires_at, created_at, last_used_at, revoked_at, replaced_by, rotated_at, allowed_cidrs, last_used_ip, request_count, environment_id
+            FROM api_keys
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            "#,
+            user_id
+        ).fetch_all(&self.pool)
+        .await?;
+
+        Ok(api_keys)
+    }
+
+    async fn get_active_api_key(
+        &self,
+        key_prefix: &str,
+        key_hash: &str
+    ) -> Result<Option<ApiKey>, sqlx::Error> {
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, user_id, name, key_prefix, access as "access: ApiKeyAccess", allowed_secrets, allowed_paths, expires_at, created_at, last_used_at, revoked_at, replaced_by, rotated_at, allowed_cidrs, last_used_ip, request_count, environment_id
+            FROM api_keys
+            WHERE key_prefix = $1 AND key_hash = $2
+                AND revoked_at IS NULL
+                AND (expires_at IS NULL OR expires_at > NOW())
+            "#,
+            key_prefix,
+            key_hash
+        ).fetch_optional(&self.pool)
+        .await?;
+
+        Ok(api_key)
+    }
+
+    async fn get_plaintext_api_keys(
+        &self
+    ) -> Result<Vec<(Uuid, String)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, key as "key!"
+            FROM api_keys
+            WHERE key IS NOT NULL
+            "#
+        ).fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.id, row.key)).collect())
+    }
+
+    async fn set_api_key_hash(
+        &self,
+        api_key_id: Uuid,
+        key_hash: String
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE api_keys
+            SET key_hash = $2, key = NULL
+            WHERE id = $1
+            "#,
+            api_key_id,
+            key_hash
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_api_key(
+        &self,
+        user_id: Uuid,
+        api_key_id: Uuid,
+        settings: ApiKeySettings
+    ) -> Result<Option<ApiKey>, sqlx::Error> {
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            UPDATE api_keys
+            SET name = $3, access = $4, allowed_secrets = $5, allowed_paths = $6, allowed_cidrs = $7, environment_id = $8, expires_at = $9
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL AND replaced_by IS NULL
+            RETURNING id, user_id, name, key_prefix, access as "access: ApiKeyAccess", allowed_secrets, allowed_paths, expires_at, created_at, last_used_at, revoked_at, replaced_by, rotated_at, allowed_cidrs, last_used_ip, request_count, environment_id
+            "#,
+            api_key_id,
+            user_id,
+            settings.name,
+            settings.access as ApiKeyAccess,
+            settings.allowed_secrets.as_deref(),
+            settings.allowed_paths.as_deref(),
+            settings.allowed_cidrs.as_deref(),
+            settings.environment_id,
+            settings.expires_at
+        ).fetch_optional(&self.pool)
+        .await?;
+
+        Ok(api_key)
+    }
+
+    async fn revoke_api_key(
+        &self,
+        user_id: Uuid,
+        api_key_id: Uuid
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE api_keys
+            SET revoked_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+            api_key_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_api_key_usage(
+        &self,
+        usages: &[ApiKeyUsage]
+    ) -> Result<(), sqlx::Error> {
+        let ids: Vec<Uuid> = usages.iter().map(|usage| usage.api_key_id).collect();
+        let counts: Vec<i64> = usages.iter().map(|usage| usage.request_count).collect();
+        let used_at: Vec<DateTime<Utc>> = usages.iter().map(|usage| usage.last_used_at).collect();
+        let ips: Vec<String> = usages.iter().map(|usage| usage.last_used_ip.clone()).collect();
+
+        sqlx::query!(
+            r#"
+            UPDATE api_keys AS k
+            SET request_count = k.request_count + u.request_count,
+                last_used_at = GREATEST(k.last_used_at, u.last_used_at),
+                last_used_ip = u.last_used_ip
+            FROM UNNEST($1::uuid[], $2::bigint[], $3::timestamptz[], $4::text[])
+                AS u(id, request_count, last_used_at, last_used_ip)
+            WHERE k.id = u.id
+            "#,
+            &ids,
+            &counts,
+            &used_at,
+            &ips
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_stale_api_keys(
+        &self,
+        user_id: Uuid,
+        unused_since: DateTime<Utc>
+    ) -> Result<Vec<ApiKey>, sqlx::Error> {
+        let api_keys = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, user_id, name, key_prefix, access as "access: ApiKeyAccess", allowed_secrets, allowed_paths, expires_at, created_at, last_used_at, revoked_at, replaced_by, rotated_at, allowed_cidrs, last_used_ip, request_count, environment_id
+            FROM api_keys
+            WHERE user_id = $1
+                AND revoked_at IS NULL
+                AND (expires_at IS NULL OR expires_at > NOW())
+                AND COALESCE(last_used_at, created_at) < $2
+            ORDER BY COALESCE(last_used_at, created_at) ASC
+            "#,
+            user_id,
+            unused_since
+        ).fetch_all(&self.pool)
+        .await?;
+
+        Ok(api_keys)
+    }
+
+    async fn rotate_api_key(
+        &self,
+        user_id: Uuid,
+        api_key_id: Uuid,
+        key_prefix: String,
+        key_hash: String,
+        grace_until: DateTime<Utc>
+    ) -> Result<Option<(ApiKey, ApiKey)>, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+
+        let previous = sqlx::query_as!(
+            ApiKey,
+            r#"
+            SELECT id, user_id, name, key_prefix, access as "access: ApiKeyAccess", allowed_secrets, allowed_paths, expires_at, created_at, last_used_at, revoked_at, replaced_by, rotated_at, allowed_cidrs, last_used_ip, request_count, environment_id
+            FROM api_keys
+            WHERE id = $1 AND user_id = $2
+                AND revoked_at IS NULL
+                AND replaced_by IS NULL
+                AND (expires_at IS NULL OR expires_at > NOW())
+            FOR UPDATE
+            "#,
+            api_key_id,
+            user_id
+        ).fetch_optional(&mut *transaction)
+        .await?;
+
+        let Some(previous) = previous else {
+            return Ok(None);
+        };
+
+        let current = sqlx::query_as!(
+            ApiKey,
+            r#"
+            INSERT INTO api_keys (user_id, name, key_prefix, key_hash, access, allowed_secrets, allowed_paths, allowed_cidrs, environment_id, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING id, user_id, name, key_prefix, access as "access: ApiKeyAccess", allowed_secrets, allowed_paths, expires_at, created_at, last_used_at, revoked_at, replaced_by, rotated_at, allowed_cidrs, last_used_ip, request_count, environment_id
+            "#,
+            user_id,
+            previous.name,
+            key_prefix,
+            key_hash,
+            previous.access as ApiKeyAccess,
+            previous.allowed_secrets.as_deref(),
+            previous.allowed_paths.as_deref(),
+            previous.allowed_cidrs.as_deref(),
+            previous.environment_id,
+            previous.expires_at
+        ).fetch_one(&mut *transaction)
+        .await?;
+
+        // The old key never outlives its own expiry, even with a long grace period.
+        let previous = sqlx::query_as!(
+            ApiKey,
+            r#"
+            UPDATE api_keys
+            SET replaced_by = $2, rotated_at = NOW(), expires_at = LEAST(COALESCE(expires_at, $3), $3)
+            WHERE id = $1
+            RETURNING id, user_id, name, key_prefix, access as "access: ApiKeyAccess", allowed_secrets, allowed_paths, expires_at, created_at, last_used_at, revoked_at, replaced_by, rotated_at, allowed_cidrs, last_used_ip, request_count, environment_id
+            "#,
+            previous.id,
+            current.id,
+            grace_until
+        ).fetch_one(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(Some((previous, current)))
+    }
+
+    // Only the latest `kept_per_key` rejections of each key are kept, so a
+    // key tried from many addresses cannot grow the table without bound.
+    async fn record_api_key_rejections(
+        &self,
+        rejections: &[ApiKeyRejectedAddress],
+        kept_per_key: i64
+    ) -> Result<(), sqlx::Error> {
+        let ids: Vec<Uuid> = rejections.iter().map(|rejection| rejection.api_key_id).collect();
+        let ips: Vec<String> = rejections.iter().map(|rejection| rejection.ip_address.clone()).collect();
+        let rejected_at: Vec<DateTime<Utc>> = rejections.iter().map(|rejection| rejection.rejected_at).collect();
+
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO api_key_rejections (api_key_id, ip_address, created_at)
+            SELECT r.api_key_id, r.ip_address, r.created_at
+            FROM UNNEST($1::uuid[], $2::text[], $3::timestamptz[]) AS r(api_key_id, ip_address, created_at)
+            WHERE EXISTS (SELECT 1 FROM api_keys WHERE api_keys.id = r.api_key_id)
+            "#,
+            &ids,
+            &ips,
+            &rejected_at
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM api_key_rejections
+            WHERE id IN (
+                SELECT id FROM (
+                    SELECT id, ROW_NUMBER() OVER (PARTITION BY api_key_id ORDER BY created_at DESC) AS position
+                    FROM api_key_rejections
+                    WHERE api_key_id = ANY($1)
+                ) ranked
+                WHERE ranked.position > $2
+            )
+            "#,
+            &ids,
+            kept_per_key
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_api_key_rejections(
+        &self,
+        api_key_id: Uuid,
+        limit: i64
+    ) -> Result<Vec<ApiKeyRejection>, sqlx::Error> {
+        let rejections = sqlx::query_as!(
+            ApiKeyRejection,
+            r#"
+            SELECT id, api_key_id, ip_address, created_at
+            FROM api_key_rejections
+            WHERE api_key_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            api_key_id,
+            limit
+        ).fetch_all(&self.pool)
+        .await?;
+
+        Ok(rejections)
+    }
+}
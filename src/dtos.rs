@@ -1,8 +1,10 @@
+use std::collections::{BTreeMap, HashMap};
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use validator::{Validate, ValidationError};
 
-use crate::models::{EncryptionMethod, User};
+use crate::{models::{ApiKey, ApiKeyAccess, ApiKeyRejection, AppRole, AppRoleSecretId, ClientCertificate, EncryptionMethod, Environment, InheritedSecret, JwtAuthRole, Project, Secret, SecretInheritance, SecretMetadata, Session, User, UserRole}, secret::SecretFilter, utils::{api_key, password_policy::PASSWORD_POLICY, secret_path}};
 
 
 
@@ -17,7 +19,7 @@ pub struct RegisterUserDto {
     pub email: String,
     #[validate(
         length(min = 1, message = "Password is required"),
-        length(min = 6, message = "Password must be at least 6 characters")
+        custom = "validate_password_policy"
     )]
     pub password: String,
 
@@ -35,19 +37,83 @@ pub struct RegisterUserDto {
 pub struct LoginUserDto {
     #[validate(length(min = 1, message = "Email is required"), email(message = "Email is invalid"))]
     pub email: String,
-    #[validate(
-        length(min = 1, message = "Password is required"),
-        length(min = 6, message = "Password must be at least 6 characters")
-    )]
+    #[validate(length(min = 1, message = "Password is required"))]
     pub password: String,
 }
 
+#[derive(Validate, Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LinkLdapIdentityDto {
+    #[validate(length(min = 1, message = "Password is required"))]
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcLinkResponseDto {
+    pub status: String,
+    #[serde(rename = "authorizationUrl")]
+    pub authorization_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcCallbackQueryDto {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Validate)]
 pub struct RequestQueryDto {
     #[validate(range(min = 1))]
     pub page: Option<usize>,
     #[validate(range(min = 1, max = 50))]
     pub limit: Option<usize>,
+    pub environment_id: Option<uuid::Uuid>,
+    // Comma-separated tags, all of which a secret must carry.
+    pub tag: Option<String>,
+    pub owner: Option<String>,
+    // Comma-separated `key=value` pairs, all of which a secret must carry.
+    #[validate(custom = "validate_label_query")]
+    pub label: Option<String>,
+}
+
+impl RequestQueryDto {
+    pub fn secret_filter(&self) -> SecretFilter {
+        let split = |list: &Option<String>| -> Vec<String> {
+            list.as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_owned)
+                .collect()
+        };
+
+        SecretFilter {
+            tags: split(&self.tag),
+            owner: self.owner.clone(),
+            labels: split(&self.label)
+                .iter()
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+                .collect(),
+        }
+    }
+}
+
+fn validate_label_query(label: &str) -> Result<(), ValidationError> {
+    let valid = label.split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .all(|pair| matches!(pair.split_once('='), Some((key, _)) if !key.trim().is_empty()));
+
+    if valid {
+        return Ok(());
+    }
+
+    let mut error = ValidationError::new("label");
+    error.message = Some("Labels must be given as `key=value` pairs separated by commas.".into());
+
+    Err(error)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,7 +122,7 @@ pub struct FilterUserDto {
     pub name: String,
     pub email: String,
     pub encryption_method: Option<EncryptionMethod>,
-    pub api_keys: Option<String>,    
+    pub role: UserRole,
     #[serde(rename = "dbConnectionExists")]               
     pub db_connection_exists: bool,  
     #[serde(rename = "createdAt")]
@@ -72,7 +138,7 @@ impl FilterUserDto {
             name: user.name.to_owned(),
             email: user.email.to_owned(),
             encryption_method: user.encryption_method, // Include this if you want it in the DTO
-            api_keys: user.api_keys.clone(), // Include this if you want it in the DTO
+            role: user.role,
             db_connection_exists: user.db_connection.is_some(), // Check if db_connection exists
             created_at: user.created_at.unwrap(),
             updated_at: user.updated_at.unwrap(),
@@ -83,6 +149,8 @@ impl FilterUserDto {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserData {
     pub user: FilterUserDto,
+    #[serde(rename = "apiKeys", skip_serializing_if = "Option::is_none")]
+    pub api_keys: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -97,6 +165,567 @@ pub struct UserLoginResponseDto {
     pub status: String,
     pub user: FilterUserDto,
     pub token: String,
+    #[serde(rename = "csrfToken")]
+    pub csrf_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterSessionDto {
+    pub id: String,
+    #[serde(rename = "ipAddress")]
+    pub ip_address: Option<String>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    pub current: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: DateTime<Utc>,
+}
+
+impl FilterSessionDto {
+    pub fn filter_session(session: &Session, current_session_id: Option<uuid::Uuid>) -> Self {
+        FilterSessionDto {
+            id: session.id.to_string(),
+            ip_address: session.ip_address.clone(),
+            user_agent: session.user_agent.clone(),
+            current: Some(session.id) == current_session_id,
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+        }
+    }
+
+    pub fn filter_sessions(sessions: &[Session], current_session_id: Option<uuid::Uuid>) -> Vec<FilterSessionDto> {
+        sessions.iter().map(|session| FilterSessionDto::filter_session(session, current_session_id)).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionListResponseDto {
+    pub status: String,
+    pub sessions: Vec<FilterSessionDto>,
+}
+
+#[derive(Debug, Validate, Default, Clone, Serialize, Deserialize)]
+pub struct ClientCertificateDto {
+    #[validate(length(min = 1, max = 100, message = "Name is required."))]
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterClientCertificateDto {
+    pub id: String,
+    pub name: String,
+    pub fingerprint: String,
+    pub subject: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl FilterClientCertificateDto {
+    pub fn filter_certificate(certificate: &ClientCertificate) -> Self {
+        FilterClientCertificateDto {
+            id: certificate.id.to_string(),
+            name: certificate.name.to_owned(),
+            fingerprint: certificate.fingerprint.clone(),
+            subject: certificate.subject.clone(),
+            created_at: certificate.created_at,
+        }
+    }
+
+    pub fn filter_certificates(certificates: &[ClientCertificate]) -> Vec<FilterClientCertificateDto> {
+        certificates.iter().map(FilterClientCertificateDto::filter_certificate).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientCertificateListResponseDto {
+    pub status: String,
+    pub certificates: Vec<FilterClientCertificateDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientCertificateResponseDto {
+    pub status: String,
+    pub certificate: FilterClientCertificateDto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BoundClaimValue {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl BoundClaimValue {
+    pub fn into_values(self) -> Vec<String> {
+        match self {
+            BoundClaimValue::One(value) => vec![value],
+            BoundClaimValue::Many(values) => values,
+        }
+    }
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct JwtAuthRoleDto {
+    #[validate(length(min = 1, max = 100, message = "Name is required."))]
+    pub name: String,
+    #[validate(length(min = 1, message = "Issuer is required."))]
+    pub issuer: String,
+    #[validate(length(min = 1, message = "Audience is required."))]
+    pub audience: String,
+    #[serde(rename = "boundClaims")]
+    pub bound_claims: HashMap<String, BoundClaimValue>,
+    #[serde(rename = "tokenTtlMinutes")]
+    #[validate(range(min = 1, message = "Token TTL must be at least one minute."))]
+    pub token_ttl_minutes: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterJwtAuthRoleDto {
+    pub id: String,
+    pub name: String,
+    pub issuer: String,
+    pub audience: String,
+    #[serde(rename = "boundClaims")]
+    pub bound_claims: HashMap<String, Vec<String>>,
+    #[serde(rename = "tokenTtlMinutes")]
+    pub token_ttl_minutes: i32,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl FilterJwtAuthRoleDto {
+    pub fn filter_role(role: &JwtAuthRole) -> Self {
+        FilterJwtAuthRoleDto {
+            id: role.id.to_string(),
+            name: role.name.to_owned(),
+            issuer: role.issuer.to_owned(),
+            audience: role.audience.to_owned(),
+            bound_claims: role.bound_claims.0.clone(),
+            token_ttl_minutes: role.token_ttl_minutes,
+            created_at: role.created_at,
+        }
+    }
+
+    pub fn filter_roles(roles: &[JwtAuthRole]) -> Vec<FilterJwtAuthRoleDto> {
+        roles.iter().map(FilterJwtAuthRoleDto::filter_role).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JwtAuthRoleListResponseDto {
+    pub status: String,
+    pub roles: Vec<FilterJwtAuthRoleDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JwtAuthRoleResponseDto {
+    pub status: String,
+    pub role: FilterJwtAuthRoleDto,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct JwtLoginDto {
+    pub role: uuid::Uuid,
+    #[validate(length(min = 1, message = "JWT is required."))]
+    pub jwt: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScopedTokenResponseDto {
+    pub status: String,
+    pub token: String,
+    pub scope: String,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct AppRoleDto {
+    #[validate(length(min = 1, max = 100, message = "Name is required."))]
+    pub name: String,
+    #[serde(rename = "allowedSecrets")]
+    pub allowed_secrets: Option<Vec<uuid::Uuid>>,
+    #[serde(rename = "tokenTtlMinutes")]
+    #[validate(range(min = 1, message = "Token TTL must be at least one minute."))]
+    pub token_ttl_minutes: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterAppRoleDto {
+    #[serde(rename = "roleId")]
+    pub role_id: String,
+    pub name: String,
+    #[serde(rename = "allowedSecrets")]
+    pub allowed_secrets: Option<Vec<uuid::Uuid>>,
+    #[serde(rename = "tokenTtlMinutes")]
+    pub token_ttl_minutes: i32,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl FilterAppRoleDto {
+    pub fn filter_approle(approle: &AppRole) -> Self {
+        FilterAppRoleDto {
+            role_id: approle.id.to_string(),
+            name: approle.name.to_owned(),
+            allowed_secrets: approle.allowed_secrets.clone(),
+            token_ttl_minutes: approle.token_ttl_minutes,
+            created_at: approle.created_at,
+        }
+    }
+
+    pub fn filter_approles(approles: &[AppRole]) -> Vec<FilterAppRoleDto> {
+        approles.iter().map(FilterAppRoleDto::filter_approle).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppRoleListResponseDto {
+    pub status: String,
+    pub roles: Vec<FilterAppRoleDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppRoleResponseDto {
+    pub status: String,
+    pub role: FilterAppRoleDto,
+}
+
+#[derive(Debug, Validate, Default, Clone, Serialize, Deserialize)]
+pub struct AppRoleSecretIdDto {
+    #[serde(rename = "ttlMinutes")]
+    #[validate(range(min = 1, message = "TTL must be at least one minute."))]
+    pub ttl_minutes: Option<i64>,
+    #[serde(rename = "numUses")]
+    #[validate(range(min = 1, message = "Number of uses must be at least one."))]
+    pub num_uses: Option<i32>,
+    #[serde(rename = "boundCidrs")]
+    pub bound_cidrs: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterAppRoleSecretIdDto {
+    pub id: String,
+    #[serde(rename = "usesRemaining")]
+    pub uses_remaining: Option<i32>,
+    #[serde(rename = "boundCidrs")]
+    pub bound_cidrs: Option<Vec<String>>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl FilterAppRoleSecretIdDto {
+    pub fn filter_secret_id(secret_id: &AppRoleSecretId) -> Self {
+        FilterAppRoleSecretIdDto {
+            id: secret_id.id.to_string(),
+            uses_remaining: secret_id.uses_remaining,
+            bound_cidrs: secret_id.bound_cidrs.clone(),
+            expires_at: secret_id.expires_at,
+            created_at: secret_id.created_at,
+        }
+    }
+
+    pub fn filter_secret_ids(secret_ids: &[AppRoleSecretId]) -> Vec<FilterAppRoleSecretIdDto> {
+        secret_ids.iter().map(FilterAppRoleSecretIdDto::filter_secret_id).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppRoleSecretIdListResponseDto {
+    pub status: String,
+    #[serde(rename = "secretIds")]
+    pub secret_ids: Vec<FilterAppRoleSecretIdDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppRoleSecretIdResponseDto {
+    pub status: String,
+    #[serde(rename = "secretId")]
+    pub secret_id: String,
+    pub metadata: FilterAppRoleSecretIdDto,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct AppRoleLoginDto {
+    #[serde(rename = "roleId")]
+    pub role_id: uuid::Uuid,
+    #[serde(rename = "secretId")]
+    #[validate(length(min = 1, message = "Secret id is required."))]
+    pub secret_id: String,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct ApiKeyDto {
+    #[validate(length(min = 1, max = 100, message = "Name is required."))]
+    pub name: String,
+    pub access: Option<ApiKeyAccess>,
+    #[serde(rename = "allowedSecrets")]
+    pub allowed_secrets: Option<Vec<uuid::Uuid>>,
+    #[serde(rename = "allowedPaths")]
+    pub allowed_paths: Option<Vec<String>>,
+    #[serde(rename = "allowedCidrs")]
+    pub allowed_cidrs: Option<Vec<String>>,
+    #[serde(rename = "environmentId")]
+    pub environment_id: Option<uuid::Uuid>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterApiKeyDto {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub access: ApiKeyAccess,
+    #[serde(rename = "allowedSecrets")]
+    pub allowed_secrets: Option<Vec<uuid::Uuid>>,
+    #[serde(rename = "allowedPaths")]
+    pub allowed_paths: Option<Vec<String>>,
+    #[serde(rename = "allowedCidrs")]
+    pub allowed_cidrs: Option<Vec<String>>,
+    #[serde(rename = "environmentId")]
+    pub environment_id: Option<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(rename = "lastUsedIp")]
+    pub last_used_ip: Option<String>,
+    #[serde(rename = "requestCount")]
+    pub request_count: i64,
+    pub revoked: bool,
+    #[serde(rename = "revokedAt")]
+    pub revoked_at: Option<DateTime<Utc>>,
+    #[serde(rename = "replacedBy")]
+    pub replaced_by: Option<String>,
+    #[serde(rename = "rotatedAt")]
+    pub rotated_at: Option<DateTime<Utc>>,
+}
+
+impl FilterApiKeyDto {
+    pub fn filter_api_key(api_key: &ApiKey) -> Self {
+        FilterApiKeyDto {
+            id: api_key.id.to_string(),
+            name: api_key.name.to_owned(),
+            prefix: api_key::mask(&api_key.key_prefix),
+            access: api_key.access,
+            allowed_secrets: api_key.allowed_secrets.clone(),
+            allowed_paths: api_key.allowed_paths.clone(),
+            allowed_cidrs: api_key.allowed_cidrs.clone(),
+            environment_id: api_key.environment_id.map(|id| id.to_string()),
+            expires_at: api_key.expires_at,
+            created_at: api_key.created_at,
+            last_used_at: api_key.last_used_at,
+            last_used_ip: api_key.last_used_ip.clone(),
+            request_count: api_key.request_count,
+            revoked: api_key.revoked_at.is_some(),
+            revoked_at: api_key.revoked_at,
+            replaced_by: api_key.replaced_by.map(|id| id.to_string()),
+            rotated_at: api_key.rotated_at,
+        }
+    }
+
+    pub fn filter_api_keys(api_keys: &[ApiKey]) -> Vec<FilterApiKeyDto> {
+        api_keys.iter().map(FilterApiKeyDto::filter_api_key).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyListResponseDto {
+    pub status: String,
+    #[serde(rename = "apiKeys")]
+    pub api_keys: Vec<FilterApiKeyDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyResponseDto {
+    pub status: String,
+    #[serde(rename = "apiKey")]
+    pub api_key: FilterApiKeyDto,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyCreatedResponseDto {
+    pub status: String,
+    pub key: String,
+    #[serde(rename = "apiKey")]
+    pub api_key: FilterApiKeyDto,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterApiKeyRejectionDto {
+    pub id: String,
+    #[serde(rename = "ipAddress")]
+    pub ip_address: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl FilterApiKeyRejectionDto {
+    pub fn filter_rejection(rejection: &ApiKeyRejection) -> Self {
+        FilterApiKeyRejectionDto {
+            id: rejection.id.to_string(),
+            ip_address: rejection.ip_address.to_owned(),
+            created_at: rejection.created_at,
+        }
+    }
+
+    pub fn filter_rejections(rejections: &[ApiKeyRejection]) -> Vec<FilterApiKeyRejectionDto> {
+        rejections.iter().map(FilterApiKeyRejectionDto::filter_rejection).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyRejectionListResponseDto {
+    pub status: String,
+    pub rejections: Vec<FilterApiKeyRejectionDto>,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct StaleApiKeysQueryDto {
+    #[validate(range(min = 1, max = 3650))]
+    pub days: Option<i64>,
+}
+
+#[derive(Debug, Validate, Default, Clone, Serialize, Deserialize)]
+pub struct RotateApiKeyDto {
+    #[serde(rename = "gracePeriodMinutes")]
+    #[validate(range(min = 0, max = 43200, message = "Grace period must be between 0 and 43200 minutes."))]
+    pub grace_period_minutes: Option<i64>,
+}
+
+// A key counts as in use once it has been used after the rotation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyRotationDto {
+    pub previous: FilterApiKeyDto,
+    pub current: FilterApiKeyDto,
+    #[serde(rename = "previousInUse")]
+    pub previous_in_use: bool,
+    #[serde(rename = "currentInUse")]
+    pub current_in_use: bool,
+}
+
+impl ApiKeyRotationDto {
+    pub fn filter_rotation(previous: &ApiKey, current: &ApiKey) -> Self {
+        let used_since_rotation = |api_key: &ApiKey| match (api_key.last_used_at, previous.rotated_at) {
+            (Some(last_used_at), Some(rotated_at)) => last_used_at >= rotated_at,
+            _ => false,
+        };
+
+        ApiKeyRotationDto {
+            previous: FilterApiKeyDto::filter_api_key(previous),
+            current: FilterApiKeyDto::filter_api_key(current),
+            previous_in_use: used_since_rotation(previous),
+            current_in_use: used_since_rotation(current),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyRotationResponseDto {
+    pub status: String,
+    pub rotation: ApiKeyRotationDto,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyRotatedResponseDto {
+    pub status: String,
+    pub key: String,
+    pub rotation: ApiKeyRotationDto,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct ProjectDto {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters."))]
+    pub name: String,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct EnvironmentDto {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters."))]
+    pub name: String,
+    pub parent_id: Option<uuid::Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterEnvironmentDto {
+    pub id: String,
+    pub project_id: String,
+    pub parent_id: Option<String>,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl FilterEnvironmentDto {
+    pub fn filter_environment(environment: &Environment) -> Self {
+        FilterEnvironmentDto {
+            id: environment.id.to_string(),
+            project_id: environment.project_id.to_string(),
+            parent_id: environment.parent_id.map(|id| id.to_string()),
+            name: environment.name.to_owned(),
+            created_at: environment.created_at,
+            updated_at: environment.updated_at,
+        }
+    }
+
+    pub fn filter_environments(environments: &[Environment]) -> Vec<FilterEnvironmentDto> {
+        environments.iter().map(FilterEnvironmentDto::filter_environment).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterProjectDto {
+    pub id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl FilterProjectDto {
+    pub fn filter_project(project: &Project) -> Self {
+        FilterProjectDto {
+            id: project.id.to_string(),
+            name: project.name.to_owned(),
+            created_at: project.created_at,
+            updated_at: project.updated_at,
+        }
+    }
+
+    pub fn filter_projects(projects: &[Project]) -> Vec<FilterProjectDto> {
+        projects.iter().map(FilterProjectDto::filter_project).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectListResponseDto {
+    pub status: String,
+    pub projects: Vec<FilterProjectDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectResponseDto {
+    pub status: String,
+    pub project: FilterProjectDto,
+    pub environments: Vec<FilterEnvironmentDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnvironmentListResponseDto {
+    pub status: String,
+    pub environments: Vec<FilterEnvironmentDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnvironmentResponseDto {
+    pub status: String,
+    pub environment: FilterEnvironmentDto,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -115,24 +744,40 @@ pub struct NameUpdateDto {
 pub struct UserPasswordUpdateDto {
     #[validate(
         length(min = 1, message = "New password is required."),
-        length(min = 6, message = "new password must be at least 6 characters")
+        custom = "validate_password_policy"
     )]
     pub new_password: String,
 
     #[validate(
         length(min = 1, message = "New password confirm is required."),
-        length(min = 6, message = "new password confirm must be at least 6 characters"),
         must_match(other = "new_password", message="new passwords do not match")
     )]
     pub new_password_confirm: String,
 
-    #[validate(
-        length(min = 1, message = "Old password is required."),
-        length(min = 6, message = "Old password must be at least 6 characters")
-    )]
+    #[validate(length(min = 1, message = "Old password is required."))]
     pub old_password: String,
 }
 
+fn validate_password_policy(password: &str) -> Result<(), ValidationError> {
+    let reasons = PASSWORD_POLICY.check(password);
+
+    if reasons.is_empty() {
+        return Ok(());
+    }
+
+    let mut error = ValidationError::new("password_policy");
+    error.message = Some(format!("Password {}", reasons.join(", ")).into());
+    Err(error)
+}
+
+#[derive(Debug, Validate, Default, Clone, Serialize, Deserialize)]
+pub struct DeleteAccountDto {
+    #[validate(length(min = 1, message = "Password is required."))]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub drop_secrets: bool,
+}
+
 #[derive(Debug, Validate, Clone, Serialize, Deserialize)]
 pub struct DatabaseDto {
     #[validate(length(min = 1, message = "Host is required."))]
@@ -159,25 +804,209 @@ fn validate_encryption_method(encryption_method: &EncryptionMethod) -> Result<()
         | EncryptionMethod::Chacha20
         | EncryptionMethod::Blowfish
         | EncryptionMethod::DESTriphleDES => Ok(()),
-        _ => Err(ValidationError::new("encryption_method must be either AES256, Chacha20, Blowfish, or DESTriphleDES")),
     }
 }
 
 #[derive(Debug, Validate, Clone, Serialize, Deserialize)]
 pub struct SaveSecretDto {
-    #[validate(length(min = 1, message = "Secret name is required."))]
+    #[validate(
+        length(min = 1, message = "Secret name is required."),
+        custom = "validate_secret_path"
+    )]
     pub secret_name: String,
     #[validate(length(min = 1, message = "Secret value is required."))]
     pub secret_value: String,
+    pub environment_id: Option<uuid::Uuid>,
+    #[serde(flatten)]
+    #[validate]
+    pub metadata: SecretMetadataDto,
+}
+
+#[derive(Debug, Validate, Clone, Default, Serialize, Deserialize)]
+pub struct SecretMetadataDto {
+    #[validate(length(max = 1000, message = "Description must be at most 1000 characters."))]
+    pub description: Option<String>,
+    #[validate(length(min = 1, max = 255, message = "Owner must be between 1 and 255 characters."))]
+    pub owner: Option<String>,
+    #[serde(default)]
+    #[validate(custom = "validate_tags")]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    #[validate(custom = "validate_labels")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl SecretMetadataDto {
+    pub fn into_metadata(self) -> SecretMetadata {
+        let mut tags = self.tags;
+        tags.sort();
+        tags.dedup();
+
+        SecretMetadata {
+            description: self.description,
+            owner: self.owner,
+            tags,
+            labels: sqlx::types::Json(self.labels),
+        }
+    }
+}
+
+// Tags and labels are matched by comma-separated query parameters, so they
+// cannot contain commas themselves.
+fn validate_tags(tags: &[String]) -> Result<(), ValidationError> {
+    let valid = tags.len() <= 50
+        && tags.iter().all(|tag| (1..=64).contains(&tag.chars().count()) && !tag.contains(',') && tag.trim() == tag);
+
+    if valid {
+        return Ok(());
+    }
+
+    let mut error = ValidationError::new("tags");
+    error.message = Some("At most 50 tags of 1 to 64 characters each, without commas or surrounding spaces.".into());
+
+    Err(error)
+}
+
+fn validate_labels(labels: &BTreeMap<String, String>) -> Result<(), ValidationError> {
+    let valid = labels.len() <= 50
+        && labels.iter().all(|(key, value)| {
+            (1..=64).contains(&key.chars().count())
+                && value.chars().count() <= 256
+                && !key.contains([',', '='])
+                && !value.contains(',')
+                && key.trim() == key
+                && value.trim() == value
+        });
+
+    if valid {
+        return Ok(());
+    }
+
+    let mut error = ValidationError::new("labels");
+    error.message = Some("At most 50 labels with keys of 1 to 64 characters and values of at most 256, without commas, `=` in keys or surrounding spaces.".into());
+
+    Err(error)
 }
 
 #[derive(Debug, Validate, Clone, Serialize, Deserialize)]
 pub struct EditSecretDto {
-    #[validate(length(min = 1, message = "Secret name is required."))]
+    #[validate(
+        length(min = 1, message = "Secret name is required."),
+        custom = "validate_secret_path"
+    )]
     pub secret_name: String,
     #[validate(length(min = 1, message = "Secret value is required."))]
     pub secret_value: String,
     pub id: uuid::Uuid,
+    pub environment_id: Option<uuid::Uuid>,
+}
+
+fn validate_secret_path(path: &str) -> Result<(), ValidationError> {
+    if secret_path::is_valid(path) {
+        return Ok(());
+    }
+
+    let mut error = ValidationError::new("secret_path");
+    error.message = Some(format!(
+        "Secret name must be a path like `payments/prod/db/password` of at most {} characters, without empty, `.` or `..` segments.",
+        secret_path::MAX_LENGTH
+    ).into());
+
+    Err(error)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnvironmentQueryDto {
+    pub environment_id: Option<uuid::Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderQueryDto {
+    pub path: Option<String>,
+    pub environment_id: Option<uuid::Uuid>,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct MoveFolderDto {
+    #[validate(length(min = 1, message = "Source folder is required."))]
+    pub from: String,
+    #[validate(length(min = 1, message = "Destination folder is required."), custom = "validate_secret_path")]
+    pub to: String,
+    pub environment_id: Option<uuid::Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderResponseDto {
+    pub environment_id: String,
+    pub path: String,
+    pub folders: Vec<String>,
+    pub secret: Vec<FilterSecretMetadataDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretDiffQueryDto {
+    pub source_environment_id: Option<uuid::Uuid>,
+    pub source_path: Option<String>,
+    pub target_environment_id: Option<uuid::Uuid>,
+    pub target_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretDiffStatus {
+    OnlyInSource,
+    OnlyInTarget,
+    Changed,
+    Unchanged,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretDiffEntryDto {
+    pub name: String,
+    pub status: SecretDiffStatus,
+    pub source_fingerprint: Option<String>,
+    pub target_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretDiffResponseDto {
+    pub status: String,
+    pub source_environment_id: String,
+    pub source_path: String,
+    pub target_environment_id: String,
+    pub target_path: String,
+    pub entries: Vec<SecretDiffEntryDto>,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct PromoteSecretsDto {
+    pub source_environment_id: uuid::Uuid,
+    pub source_path: Option<String>,
+    pub target_environment_id: uuid::Uuid,
+    pub target_path: Option<String>,
+    #[validate(length(min = 1, max = 100, message = "Promote between 1 and 100 secrets at once."))]
+    pub names: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretPromotion {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PromotedSecretDto {
+    pub name: String,
+    pub result: SecretPromotion,
+    pub version: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PromoteSecretsResponseDto {
+    pub status: String,
+    pub secrets: Vec<PromotedSecretDto>,
 }
 
 #[derive(Serialize, Deserialize, Validate)]
@@ -193,28 +1022,40 @@ pub struct RequestQuerySecretVersionDto {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SecretResponse {
     pub id: uuid::Uuid,
+    pub environment_id: Option<uuid::Uuid>,
+    pub inheritance: Option<SecretInheritance>,
     pub secret_name: String,
     pub secret_value: String,
     pub version: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub metadata: Option<SecretMetadata>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FilterSecretDto {
     pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inheritance: Option<SecretInheritance>,
     pub secret_name: String,
     pub secret_value: String,
     pub version: i32,
+    #[serde(flatten)]
+    pub metadata: Option<SecretMetadata>,
 }
 
 impl FilterSecretDto {
     pub fn filter_secret(secret: &SecretResponse) -> Self {
         FilterSecretDto {
             id: secret.id.to_string(),
+            environment_id: secret.environment_id.map(|id| id.to_string()),
+            inheritance: secret.inheritance,
             secret_name: secret.secret_name.to_string(),
             secret_value: secret.secret_value.to_string(),
             version: secret.version, 
+            metadata: secret.metadata.clone(),
         }
     }
 
@@ -231,10 +1072,91 @@ pub struct SecretResponseDto {
 }
 
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilterSecretMetadataDto {
+    pub id: String,
+    pub environment_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inheritance: Option<SecretInheritance>,
+    pub secret_name: String,
+    pub version: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub metadata: SecretMetadata,
+}
+
+impl FilterSecretMetadataDto {
+    pub fn filter_secret(secret: &Secret) -> Self {
+        FilterSecretMetadataDto {
+            id: secret.id.to_string(),
+            environment_id: secret.environment_id.to_string(),
+            inheritance: None,
+            secret_name: secret.secret_name.to_owned(),
+            version: secret.version,
+            created_at: secret.created_at,
+            updated_at: secret.updated_at,
+            deleted_at: secret.deleted_at,
+            metadata: secret.metadata.clone(),
+        }
+    }
+
+    pub fn filter_secrets(secrets: &[Secret]) -> Vec<FilterSecretMetadataDto> {
+        secrets.iter().map(FilterSecretMetadataDto::filter_secret).collect()
+    }
+
+    pub fn filter_inherited_secret(secret: &InheritedSecret) -> Self {
+        FilterSecretMetadataDto {
+            inheritance: Some(secret.inheritance()),
+            ..FilterSecretMetadataDto::filter_secret(&secret.secret)
+        }
+    }
+
+    pub fn filter_inherited_secrets(secrets: &[InheritedSecret]) -> Vec<FilterSecretMetadataDto> {
+        secrets.iter().map(FilterSecretMetadataDto::filter_inherited_secret).collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretMetadataResponseDto {
+    pub secret: Vec<FilterSecretMetadataDto>,
+    pub total_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SingleSecretResponseDto {
+    pub secret: FilterSecretDto,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct MachineSecretValueDto {
+    #[validate(length(min = 1, message = "Secret value is required."))]
+    pub secret_value: String,
+}
+
+#[derive(Debug, Validate, Clone, Serialize, Deserialize)]
+pub struct SecretBatchDto {
+    #[validate(length(max = 100, message = "At most 100 ids can be fetched at once."))]
+    pub ids: Option<Vec<uuid::Uuid>>,
+    #[validate(length(max = 100, message = "At most 100 names can be fetched at once."))]
+    pub names: Option<Vec<String>>,
+    pub environment_id: Option<uuid::Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretBatchResponseDto {
+    pub secret: Vec<FilterSecretDto>,
+    pub missing: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RequestQuerySecretByKeyDto {
-    pub key: String,
-    pub secret: uuid::Uuid,
+    pub key: Option<String>,
+    pub secret: Option<uuid::Uuid>,
+    #[serde(alias = "path")]
+    pub name: Option<String>,
+    pub environment_id: Option<uuid::Uuid>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
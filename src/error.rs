@@ -28,11 +28,21 @@ pub enum ErrorMessage {
     EmailExist,
     UserNoLongerExist,
     TokenNotProvided,
+    SessionRevoked,
+    CertificateNotBound,
+    CsrfTokenMismatch,
+    InsufficientScope,
+    InvalidApiKey,
+    ApiKeyNotProvided,
+    AddressNotAllowed,
+    AdminRequired,
+    ApiKeyReadOnly,
+    IdentityNotLinked,
 }
 
-impl ToString for ErrorMessage {
-    fn to_string(&self) -> String {
-        self.to_str().to_owned()
+impl fmt::Display for ErrorMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
     }
 }
 
@@ -49,6 +59,16 @@ impl ErrorMessage {
             ErrorMessage::ExceededMaxPasswordLength(max_length) => format!("Password must not be more than {} characters", max_length),
             ErrorMessage::InvalidToken => "Authentication token is invalid or expired".to_string(),
             ErrorMessage::TokenNotProvided => "You are not logged in, please provide a token".to_string(),
+            ErrorMessage::SessionRevoked => "This session has been revoked, please log in again".to_string(),
+            ErrorMessage::CertificateNotBound => "Client certificate is not bound to any user".to_string(),
+            ErrorMessage::CsrfTokenMismatch => "CSRF token is missing or does not match".to_string(),
+            ErrorMessage::InsufficientScope => "This token is not allowed to access this resource".to_string(),
+            ErrorMessage::InvalidApiKey => "API key is invalid, expired or revoked".to_string(),
+            ErrorMessage::ApiKeyNotProvided => "Please provide an API key in the Authorization or X-API-Key header".to_string(),
+            ErrorMessage::AddressNotAllowed => "This API key cannot be used from your address".to_string(),
+            ErrorMessage::AdminRequired => "Only admins can manage machine credentials".to_string(),
+            ErrorMessage::ApiKeyReadOnly => "This API key is read-only".to_string(),
+            ErrorMessage::IdentityNotLinked => "An account with this email already exists. Sign in to it and link this identity from your account first".to_string(),
         }
     }
 }
@@ -88,6 +108,13 @@ impl HttpError {
         }
     }
 
+    pub fn not_found(message: impl Into<String>) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::NOT_FOUND,
+        }
+    }
+
     pub fn unauthorized(message: impl Into<String>) -> Self {
         HttpError {
             message: message.into(),
@@ -95,6 +122,13 @@ impl HttpError {
         }
     }
 
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::FORBIDDEN,
+        }
+    }
+
     pub fn into_http_response(self) -> Response {
         let json_response = Json(ErrorResponse {
             status: "fail".to_string(),
@@ -0,0 +1,344 @@
+use std::sync::Arc;
+
+use axum::{extract::{Path, Query}, http::StatusCode, response::IntoResponse, routing::{get, post}, Extension, Json, Router};
+use chrono::{Duration, Utc};
+use validator::Validate;
+
+use crate::{db::{ApiKeyExt, ApiKeySettings}, dtos::{ApiKeyCreatedResponseDto, ApiKeyDto, ApiKeyListResponseDto, ApiKeyRejectionListResponseDto, ApiKeyResponseDto, ApiKeyRotatedResponseDto, ApiKeyRotationDto, ApiKeyRotationResponseDto, FilterApiKeyDto, FilterApiKeyRejectionDto, Response, RotateApiKeyDto, StaleApiKeysQueryDto}, error::HttpError, middleware::JWTAuthMiddleware, models::{ApiKey, ApiKeyAccess, User}, project::{PostgresProjectRepository, ProjectRepository}, utils::{api_key, cidr, connect_user_database::connect_to_user_database, generate_key::generate_api_key, secret_path}, AppState};
+
+pub fn api_keys_handler() -> Router {
+    Router::new()
+        .route("/", get(get_api_keys).post(create_api_key))
+        .route("/stale", get(get_stale_api_keys))
+        .route("/:id", get(get_api_key).put(update_api_key).delete(revoke_api_key))
+        .route("/:id/rotate", post(rotate_api_key))
+        .route("/:id/rotation", get(get_api_key_rotation))
+        .route("/:id/rejections", get(get_api_key_rejections))
+}
+
+fn validate_api_key(body: &ApiKeyDto) -> Result<(), HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    if body.allowed_secrets.as_ref().is_some_and(|allowed_secrets| allowed_secrets.is_empty()) {
+        return Err(HttpError::bad_request("allowedSecrets must not be empty, leave it out to allow every secret"));
+    }
+
+    if let Some(allowed_paths) = &body.allowed_paths {
+        if allowed_paths.is_empty() {
+            return Err(HttpError::bad_request("allowedPaths must not be empty, leave it out to allow every secret"));
+        }
+
+        if let Some(invalid) = allowed_paths.iter().find(|pattern| !secret_path::is_valid_pattern(pattern)) {
+            return Err(HttpError::bad_request(format!("Invalid path `{}`", invalid)));
+        }
+    }
+
+    if let Some(allowed_cidrs) = &body.allowed_cidrs {
+        if allowed_cidrs.is_empty() {
+            return Err(HttpError::bad_request("allowedCidrs must not be empty, leave it out to allow every address"));
+        }
+
+        if let Some(invalid) = allowed_cidrs.iter().find(|value| cidr::parse(value).is_none()) {
+            return Err(HttpError::bad_request(format!("Invalid CIDR `{}`", invalid)));
+        }
+    }
+
+    if body.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(HttpError::bad_request("expiresAt must be in the future"));
+    }
+
+    Ok(())
+}
+
+// Environments live in the user's own database, so a key can only be scoped to
+// one after checking it exists there.
+async fn check_environment(user: &User, environment_id: Option<uuid::Uuid>) -> Result<(), HttpError> {
+    let Some(environment_id) = environment_id else {
+        return Ok(());
+    };
+
+    let db_connection = &user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::bad_request("Set up a database before scoping keys to an environment"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    PostgresProjectRepository::new(&user_db_pool)
+        .get_environment(environment_id)
+        .await?;
+
+    Ok(())
+}
+
+fn api_key_settings(body: ApiKeyDto) -> ApiKeySettings {
+    ApiKeySettings {
+        name: body.name,
+        access: body.access.unwrap_or(ApiKeyAccess::Read),
+        allowed_secrets: body.allowed_secrets,
+        allowed_paths: body.allowed_paths,
+        allowed_cidrs: body.allowed_cidrs,
+        environment_id: body.environment_id,
+        expires_at: body.expires_at,
+    }
+}
+
+pub async fn get_api_keys(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let api_keys = app_state.db_client
+        .get_api_keys(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = ApiKeyListResponseDto {
+        status: "success".to_string(),
+        api_keys: FilterApiKeyDto::filter_api_keys(&api_keys),
+    };
+
+    Ok(Json(response))
+}
+
+// Active keys that have not been used for `days` days (30 by default); keys
+// that were never used count from their creation.
+pub async fn get_stale_api_keys(
+    Query(query_params): Query<StaleApiKeysQueryDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let days = query_params.days.unwrap_or(30);
+
+    let api_keys = app_state.db_client
+        .get_stale_api_keys(user.user.id, Utc::now() - Duration::days(days))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = ApiKeyListResponseDto {
+        status: "success".to_string(),
+        api_keys: FilterApiKeyDto::filter_api_keys(&api_keys),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_api_key(
+    Path(api_key_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let api_key = app_state.db_client
+        .get_api_keys(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .into_iter()
+        .find(|api_key| api_key.id == api_key_id)
+        .ok_or_else(|| HttpError::not_found("API key not found"))?;
+
+    let response = ApiKeyResponseDto {
+        status: "success".to_string(),
+        api_key: FilterApiKeyDto::filter_api_key(&api_key),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn create_api_key(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<ApiKeyDto>
+) -> Result<impl IntoResponse, HttpError> {
+    validate_api_key(&body)?;
+    check_environment(&user.user, body.environment_id).await?;
+
+    let key = generate_api_key(&app_state.env.api_key_environment);
+
+    let api_key = app_state.db_client
+        .save_api_key(
+            user.user.id,
+            api_key::prefix(&key),
+            api_key::hash(&app_state.env.api_key_secret, &key),
+            api_key_settings(body),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(ApiKeyCreatedResponseDto {
+        status: "success".to_string(),
+        key,
+        api_key: FilterApiKeyDto::filter_api_key(&api_key),
+    })))
+}
+
+pub async fn update_api_key(
+    Path(api_key_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<ApiKeyDto>
+) -> Result<impl IntoResponse, HttpError> {
+    validate_api_key(&body)?;
+    check_environment(&user.user, body.environment_id).await?;
+
+    // A rotated key only lives out its grace period; changing it, and its
+    // expiry above all, would keep it alive next to its successor.
+    let rotated = app_state.db_client
+        .get_api_keys(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .into_iter()
+        .find(|api_key| api_key.id == api_key_id)
+        .ok_or_else(|| HttpError::not_found("API key not found"))?
+        .replaced_by
+        .is_some();
+
+    if rotated {
+        return Err(HttpError::unique_constraint_violation("API key has been rotated; change its successor instead"));
+    }
+
+    let api_key = app_state.db_client
+        .update_api_key(user.user.id, api_key_id, api_key_settings(body))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::not_found("API key not found"))?;
+
+    let response = ApiKeyResponseDto {
+        status: "success".to_string(),
+        api_key: FilterApiKeyDto::filter_api_key(&api_key),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn revoke_api_key(
+    Path(api_key_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let revoked = app_state.db_client
+        .revoke_api_key(user.user.id, api_key_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !revoked {
+        return Err(HttpError::not_found("API key not found"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "API key revoked successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn rotate_api_key(
+    Path(api_key_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    body: Option<Json<RotateApiKeyDto>>
+) -> Result<impl IntoResponse, HttpError> {
+    let body = body.map(|Json(body)| body).unwrap_or_default();
+
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let api_key = app_state.db_client
+        .get_api_keys(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .into_iter()
+        .find(|api_key| api_key.id == api_key_id)
+        .ok_or_else(|| HttpError::not_found("API key not found"))?;
+
+    if api_key.replaced_by.is_some() {
+        return Err(HttpError::unique_constraint_violation("API key has already been rotated"));
+    }
+
+    let grace_period = body.grace_period_minutes.unwrap_or(app_state.env.api_key_rotation_grace_minutes);
+    let key = generate_api_key(&app_state.env.api_key_environment);
+
+    let (previous, current) = app_state.db_client
+        .rotate_api_key(
+            user.user.id,
+            api_key_id,
+            api_key::prefix(&key),
+            api_key::hash(&app_state.env.api_key_secret, &key),
+            Utc::now() + Duration::minutes(grace_period),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::not_found("API key not found, revoked or expired"))?;
+
+    Ok((StatusCode::CREATED, Json(ApiKeyRotatedResponseDto {
+        status: "success".to_string(),
+        key,
+        rotation: ApiKeyRotationDto::filter_rotation(&previous, &current),
+    })))
+}
+
+// Reports the latest rotation the key took part in, whether it was the key
+// being replaced or the successor.
+pub async fn get_api_key_rotation(
+    Path(api_key_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let api_keys = app_state.db_client
+        .get_api_keys(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let find = |id: uuid::Uuid| api_keys.iter().find(|api_key| api_key.id == id);
+
+    let api_key = find(api_key_id)
+        .ok_or_else(|| HttpError::not_found("API key not found"))?;
+
+    let pair: Option<(&ApiKey, &ApiKey)> = match api_key.replaced_by.and_then(find) {
+        Some(successor) => Some((api_key, successor)),
+        None => api_keys
+            .iter()
+            .find(|candidate| candidate.replaced_by == Some(api_key.id))
+            .map(|predecessor| (predecessor, api_key)),
+    };
+
+    let (previous, current) = pair
+        .ok_or_else(|| HttpError::not_found("API key has not been rotated"))?;
+
+    let response = ApiKeyRotationResponseDto {
+        status: "success".to_string(),
+        rotation: ApiKeyRotationDto::filter_rotation(previous, current),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_api_key_rejections(
+    Path(api_key_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let owns_key = app_state.db_client
+        .get_api_keys(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .iter()
+        .any(|api_key| api_key.id == api_key_id);
+
+    if !owns_key {
+        return Err(HttpError::not_found("API key not found"));
+    }
+
+    let rejections = app_state.db_client
+        .get_api_key_rejections(api_key_id, api_key::REJECTIONS_KEPT)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = ApiKeyRejectionListResponseDto {
+        status: "success".to_string(),
+        rejections: FilterApiKeyRejectionDto::filter_rejections(&rejections),
+    };
+
+    Ok(Json(response))
+}
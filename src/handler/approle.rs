@@ -0,0 +1,252 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{extract::{ConnectInfo, Path}, http::{HeaderMap, StatusCode}, response::IntoResponse, routing::{delete, get}, Extension, Json, Router};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use validator::Validate;
+
+use crate::{db::{AppRoleExt, UserExt}, dtos::{AppRoleDto, AppRoleListResponseDto, AppRoleLoginDto, AppRoleResponseDto, AppRoleSecretIdDto, AppRoleSecretIdListResponseDto, AppRoleSecretIdResponseDto, FilterAppRoleDto, FilterAppRoleSecretIdDto, Response, ScopedTokenResponseDto}, error::HttpError, middleware::JWTAuthMiddleware, models::AppRole, utils::{cidr, oidc, token::{self, SECRETS_READ_SCOPE}}, AppState};
+
+const DEFAULT_TOKEN_TTL_MINUTES: i32 = 15;
+
+pub fn approles_handler() -> Router {
+    Router::new()
+        .route("/", get(get_approles).post(create_approle))
+        .route("/:id", delete(delete_approle))
+        .route("/:id/secret-ids", get(get_secret_ids).post(generate_secret_id))
+        .route("/:id/secret-ids/:secret_id", delete(delete_secret_id))
+}
+
+fn hash_secret_id(secret_id: &str) -> String {
+    format!("{:x}", Sha256::digest(secret_id.as_bytes()))
+}
+
+pub async fn approle_login(
+    ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<AppRoleLoginDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let rejected = || HttpError::unauthorized("Role id or secret id is invalid");
+
+    let approle = app_state.db_client
+        .get_approle(body.role_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(rejected)?;
+
+    let secret_id = app_state.db_client
+        .get_approle_secret_id(approle.id, &hash_secret_id(&body.secret_id))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(rejected)?;
+
+    if let Some(bound_cidrs) = &secret_id.bound_cidrs {
+        let client_ip = cidr::client_ip(client_addr.ip(), &request_headers, &app_state.env.trusted_proxy_cidrs);
+        if !cidr::contains(bound_cidrs, client_ip) {
+            return Err(rejected());
+        }
+    }
+
+    let consumed = app_state.db_client
+        .consume_approle_secret_id(secret_id.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !consumed {
+        return Err(rejected());
+    }
+
+    let user = app_state.db_client
+        .get_user(Some(approle.user_id), None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(rejected)?;
+
+    let ttl_minutes = i64::from(approle.token_ttl_minutes).min(app_state.env.jwt_maxage);
+
+    let token = token::create_scoped_token(
+        &user.id.to_string(),
+        SECRETS_READ_SCOPE,
+        Some(&approle.id.to_string()),
+        &app_state.signing_keys,
+        ttl_minutes
+    )
+    .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(ScopedTokenResponseDto {
+        status: "success".to_string(),
+        token,
+        scope: SECRETS_READ_SCOPE.to_string(),
+        expires_at: Utc::now() + Duration::minutes(ttl_minutes),
+    }))
+}
+
+pub async fn get_approles(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let approles = app_state.db_client
+        .get_approles(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = AppRoleListResponseDto {
+        status: "success".to_string(),
+        roles: FilterAppRoleDto::filter_approles(&approles),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn create_approle(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<AppRoleDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    if body.allowed_secrets.as_ref().is_some_and(|allowed_secrets| allowed_secrets.is_empty()) {
+        return Err(HttpError::bad_request("allowedSecrets must not be empty, leave it out to allow every secret"));
+    }
+
+    let token_ttl_minutes = body.token_ttl_minutes.unwrap_or(DEFAULT_TOKEN_TTL_MINUTES);
+
+    if i64::from(token_ttl_minutes) > app_state.env.jwt_maxage {
+        return Err(HttpError::bad_request(format!("Token TTL must not exceed {} minutes", app_state.env.jwt_maxage)));
+    }
+
+    let result = app_state.db_client
+        .save_approle(user.user.id, body.name, body.allowed_secrets, token_ttl_minutes)
+        .await;
+
+    match result {
+        Ok(approle) => Ok((StatusCode::CREATED, Json(AppRoleResponseDto {
+            status: "success".to_string(),
+            role: FilterAppRoleDto::filter_approle(&approle),
+        }))),
+        Err(sqlx::Error::Database(db_err)) => {
+            if db_err.is_unique_violation() {
+                Err(HttpError::unique_constraint_violation("A role with this name already exists"))
+            } else {
+                Err(HttpError::server_error(db_err.to_string()))
+            }
+        }
+        Err(e) => Err(HttpError::server_error(e.to_string()))
+    }
+}
+
+pub async fn delete_approle(
+    Path(approle_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let deleted = app_state.db_client
+        .delete_approle(user.user.id, approle_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Err(HttpError::not_found("Role not found"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Role deleted successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+async fn get_owned_approle(app_state: &AppState, user: &JWTAuthMiddleware, approle_id: uuid::Uuid) -> Result<AppRole, HttpError> {
+    app_state.db_client
+        .get_approle(approle_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .filter(|approle| approle.user_id == user.user.id)
+        .ok_or_else(|| HttpError::not_found("Role not found"))
+}
+
+pub async fn get_secret_ids(
+    Path(approle_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let approle = get_owned_approle(&app_state, &user, approle_id).await?;
+
+    let secret_ids = app_state.db_client
+        .get_approle_secret_ids(approle.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = AppRoleSecretIdListResponseDto {
+        status: "success".to_string(),
+        secret_ids: FilterAppRoleSecretIdDto::filter_secret_ids(&secret_ids),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn generate_secret_id(
+    Path(approle_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<AppRoleSecretIdDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let approle = get_owned_approle(&app_state, &user, approle_id).await?;
+
+    if let Some(bound_cidrs) = &body.bound_cidrs {
+        if bound_cidrs.is_empty() {
+            return Err(HttpError::bad_request("boundCidrs must not be empty, leave it out to allow every address"));
+        }
+
+        if let Some(invalid) = bound_cidrs.iter().find(|value| cidr::parse(value).is_none()) {
+            return Err(HttpError::bad_request(format!("Invalid CIDR `{}`", invalid)));
+        }
+    }
+
+    let secret_id = oidc::random_token(40);
+    let expires_at = body.ttl_minutes.map(|ttl_minutes| Utc::now() + Duration::minutes(ttl_minutes));
+
+    let saved = app_state.db_client
+        .save_approle_secret_id(approle.id, hash_secret_id(&secret_id), body.num_uses, body.bound_cidrs, expires_at)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok((StatusCode::CREATED, Json(AppRoleSecretIdResponseDto {
+        status: "success".to_string(),
+        secret_id,
+        metadata: FilterAppRoleSecretIdDto::filter_secret_id(&saved),
+    })))
+}
+
+pub async fn delete_secret_id(
+    Path((approle_id, secret_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let approle = get_owned_approle(&app_state, &user, approle_id).await?;
+
+    let deleted = app_state.db_client
+        .delete_approle_secret_id(approle.id, secret_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Err(HttpError::not_found("Secret id not found"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Secret id revoked successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
@@ -1,15 +1,17 @@
-use std::sync::Arc;
+use std::{net::{IpAddr, SocketAddr}, sync::Arc};
 
-use axum::{http::{header, HeaderMap, StatusCode}, response::IntoResponse, routing::post, Extension, Json, Router};
-use axum_extra::extract::cookie::Cookie;
+use axum::{extract::ConnectInfo, http::{header, HeaderMap, StatusCode}, response::IntoResponse, routing::post, Extension, Json, Router};
 use validator::Validate;
 
-use crate::{db::UserExt, dtos::{FilterUserDto, LoginUserDto, RegisterUserDto, Response, UserLoginResponseDto}, error::{ErrorMessage, HttpError}, utils::{generate_key::generate_api_key, password, token}, AppState};
+use crate::{db::{IdentityExt, SessionExt, UserExt}, dtos::{FilterUserDto, LinkLdapIdentityDto, LoginUserDto, RegisterUserDto, Response, UserLoginResponseDto}, error::{ErrorMessage, HttpError}, handler::{approle::approle_login, jwt_auth::jwt_login, oidc::oidc_handler}, middleware::JWTAuthMiddleware, models::User, utils::{cookie::{self, CSRF_COOKIE, TOKEN_COOKIE}, ldap, oidc, password, password_policy::PASSWORD_POLICY, token}, AppState};
 
 pub fn auth_handler() -> Router {
     Router::new()
         .route("/register", post(register))
         .route("/login", post(login))
+        .route("/jwt", post(jwt_login))
+        .route("/approle/login", post(approle_login))
+        .nest("/oidc", oidc_handler())
 }
 
 pub async fn register(
@@ -19,17 +21,22 @@ pub async fn register(
     body.validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let hash_password = password::hash(&body.password)
+    let hash_password = password::hash(&app_state.env.password_hashing, &body.password)
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let api_key = &generate_api_key();
-
     let result = app_state.db_client
-            .save_user(&body.name, &body.email, &hash_password, api_key)
+            .save_user(&body.name, &body.email, &hash_password)
             .await;
 
     match result {
-        Ok(_user) => {
+        Ok(user) => {
+            if PASSWORD_POLICY.history_size > 0 {
+                app_state.db_client
+                    .save_password_history(user.id, hash_password, PASSWORD_POLICY.history_size)
+                    .await
+                    .map_err(|e| HttpError::server_error(e.to_string()))?;
+            }
+
             Ok((StatusCode::CREATED, Json(Response {
                 status: "success",
                 message: "Registration successful! You can now log in your account.".to_string()
@@ -51,55 +58,200 @@ pub async fn register(
 
 pub async fn login(
     Extension(app_state): Extension<Arc<AppState>>,
+    ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
     Json(body): Json<LoginUserDto>
 ) -> Result<impl IntoResponse, HttpError> {
     body.validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let result = app_state.db_client
-        .get_user(None, None, Some(&body.email), None)
+        .get_user(None, None, Some(&body.email))
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    // An unreachable directory must not lock out local accounts, and neither
+    // must a directory entry for the same email that the account was never
+    // linked to. Directory accounts only have a random local password, so
+    // they stay locked out either way.
+    let directory_user = match &app_state.env.ldap {
+        Some(ldap_config) => match ldap::authenticate(ldap_config, &body.email, &body.password).await {
+            Ok(directory_user) => directory_user.map(|directory_user| (ldap_config, directory_user)),
+            Err(err) => {
+                if err.status == StatusCode::INTERNAL_SERVER_ERROR {
+                    println!("🔥 LDAP authentication failed, falling back to the local password: {}", err.message);
+                }
+
+                None
+            }
+        },
+        None => None,
+    };
+
+    if let Some((ldap_config, directory_user)) = directory_user {
+        let role = ldap::map_role(ldap_config, &directory_user.groups);
+
+        let linked_user = app_state.db_client
+            .get_user_by_identity(ldap::IDENTITY_ISSUER, &directory_user.dn)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        let user = match (linked_user, result) {
+            (Some(user), _) => user,
+            // Registration does not verify emails, so a local account with
+            // this email may belong to someone else. Its owner links it while
+            // signed in.
+            (None, Some(_)) => {
+                return Err(HttpError::unique_constraint_violation(ErrorMessage::IdentityNotLinked.to_string()));
+            }
+            (None, None) => {
+                // Directory accounts get a random local password, so the
+                // directory stays the only way to sign in.
+                let hash_password = password::hash(&app_state.env.password_hashing, oidc::random_token(32))
+                    .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+                let name = directory_user.name.unwrap_or_else(|| body.email.clone());
+
+                app_state.db_client
+                    .save_user_with_identity(&name, &body.email, &hash_password, ldap::IDENTITY_ISSUER, &directory_user.dn)
+                    .await
+                    .map_err(|e| HttpError::server_error(e.to_string()))?
+            }
+        };
+
+        let user = if user.role != role {
+            app_state.db_client
+                .update_user_role(user.id, role)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+        } else {
+            user
+        };
+
+        return login_response(&app_state, &user, client_addr.ip(), &request_headers).await;
+    }
+
     let user = result.ok_or(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()))?;
 
-    let password_matchs = password::compare(&body.password, &user.password)
+    let password_matchs = password::compare(&app_state.env.password_hashing, &body.password, &user.password)
         .map_err(|_| HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()))?;
 
     if password_matchs {
-        let token = token::create_token(
-            &user.id.to_string(), 
-            &app_state.env.jwt_secret.as_bytes(), 
-            app_state.env.jwt_maxage
-        ).
-        map_err(|e| HttpError::server_error(e.to_string()))?;
-
-        let cookie_duration = time::Duration::minutes(app_state.env.jwt_maxage * 60);
-        let cookie = Cookie::build(("token", token.clone()))
-            .path("/")
-            .max_age(cookie_duration)
-            .http_only(true)
-            .build();
-
-        let filter_user = FilterUserDto::filter_user(&user);
-
-        let response = Json(UserLoginResponseDto {
-            status: "success".to_string(),
-            user: filter_user,
-            token,
-        });
-
-        let mut headers = HeaderMap::new();
-        headers.append(
-            header::SET_COOKIE,
-            cookie.to_string().parse().unwrap(), 
-        );
-
-        let mut response = response.into_response();
-        response.headers_mut().extend(headers);
-
-        Ok(response)
+        let user = if password::needs_rehash(&app_state.env.password_hashing, &user.password) {
+            let hash_password = password::hash(&app_state.env.password_hashing, &body.password)
+                .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+            app_state.db_client
+                .update_user_password(user.id, hash_password)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+        } else {
+            user
+        };
+
+        login_response(&app_state, &user, client_addr.ip(), &request_headers).await
     } else {
         Err(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()))
     }
+}
+
+// Links the directory entry for the signed-in user's email to their account,
+// after checking the directory password. From then on the directory password
+// signs in to the account and the role follows LDAP_GROUP_ROLES.
+pub async fn link_ldap_identity(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<LinkLdapIdentityDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let ldap_config = app_state.env.ldap.as_ref()
+        .ok_or_else(|| HttpError::bad_request("LDAP login is not configured"))?;
+
+    let directory_user = ldap::authenticate(ldap_config, &user.user.email, &body.password)
+        .await?
+        .ok_or_else(|| HttpError::not_found("The directory has no entry for your email"))?;
+
+    let linked_user = app_state.db_client
+        .get_user_by_identity(ldap::IDENTITY_ISSUER, &directory_user.dn)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    match linked_user {
+        Some(linked_user) if linked_user.id != user.user.id => {
+            return Err(HttpError::unique_constraint_violation("This identity is already linked to another account"));
+        }
+        Some(_) => {}
+        None => {
+            app_state.db_client
+                .save_identity(user.user.id, ldap::IDENTITY_ISSUER, &directory_user.dn)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?;
+        }
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Directory account linked successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn login_response(
+    app_state: &AppState,
+    user: &User,
+    client_ip: IpAddr,
+    request_headers: &HeaderMap,
+) -> Result<axum::response::Response, HttpError> {
+    let user_agent = request_headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+
+    let session = app_state.db_client
+        .create_session(user.id, Some(client_ip.to_string()), user_agent)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let token = token::create_token(
+        &user.id.to_string(), 
+        &session.id.to_string(), 
+        &app_state.signing_keys, 
+        app_state.env.jwt_maxage
+    ).
+    map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let cookie_duration = time::Duration::minutes(app_state.env.jwt_maxage * 60);
+    let token_cookie = cookie::build(&app_state.env.cookie, TOKEN_COOKIE, token.clone(), cookie_duration, true);
+
+    // Double-submit token: readable by the client, which sends it back in the
+    // X-CSRF-Token header on state-changing requests.
+    let csrf_token = oidc::random_token(32);
+    let csrf_cookie = cookie::build(&app_state.env.cookie, CSRF_COOKIE, csrf_token.clone(), cookie_duration, false);
+
+    let filter_user = FilterUserDto::filter_user(user);
+
+    let response = Json(UserLoginResponseDto {
+        status: "success".to_string(),
+        user: filter_user,
+        token,
+        csrf_token,
+    });
+
+    let mut headers = HeaderMap::new();
+    headers.append(
+        header::SET_COOKIE,
+        token_cookie.to_string().parse().unwrap(), 
+    );
+    headers.append(
+        header::SET_COOKIE,
+        csrf_cookie.to_string().parse().unwrap(), 
+    );
+
+    let mut response = response.into_response();
+    response.headers_mut().extend(headers);
+
+    Ok(response)
 }
\ No newline at end of file
@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use axum::{response::IntoResponse, routing::get, Extension, Json, Router};
+
+use crate::AppState;
+
+pub fn jwks_handler() -> Router {
+    Router::new()
+        .route("/jwks.json", get(get_jwks))
+}
+
+pub async fn get_jwks(
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> impl IntoResponse {
+    Json(app_state.signing_keys.jwks())
+}
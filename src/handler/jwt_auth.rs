@@ -0,0 +1,155 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, routing::{delete, get}, Extension, Json, Router};
+use chrono::{Duration, Utc};
+use validator::Validate;
+
+use crate::{db::{JwtAuthRoleExt, UserExt}, dtos::{FilterJwtAuthRoleDto, JwtAuthRoleDto, JwtAuthRoleListResponseDto, JwtAuthRoleResponseDto, JwtLoginDto, Response, ScopedTokenResponseDto}, error::HttpError, middleware::JWTAuthMiddleware, utils::{token::{self, SECRETS_READ_SCOPE}, workload_identity}, AppState};
+
+const DEFAULT_TOKEN_TTL_MINUTES: i32 = 15;
+
+pub fn jwt_roles_handler() -> Router {
+    Router::new()
+        .route("/", get(get_jwt_roles).post(create_jwt_role))
+        .route("/:id", delete(delete_jwt_role))
+}
+
+pub async fn jwt_login(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(body): Json<JwtLoginDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let rejected = || HttpError::unauthorized("JWT is not allowed to log in with this role");
+
+    let role = app_state.db_client
+        .get_jwt_auth_role(body.role)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(rejected)?;
+
+    // Roles only stay usable while their issuer is still trusted by the server.
+    let issuer = app_state.env.workload_issuers
+        .iter()
+        .find(|issuer| issuer.issuer == role.issuer)
+        .ok_or_else(rejected)?;
+
+    let claims = workload_identity::verify(issuer, &body.jwt, &role.audience).await?;
+
+    if !workload_identity::matches_bound_claims(&role.bound_claims, &claims) {
+        return Err(rejected());
+    }
+
+    let user = app_state.db_client
+        .get_user(Some(role.user_id), None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(rejected)?;
+
+    let ttl_minutes = i64::from(role.token_ttl_minutes).min(app_state.env.jwt_maxage);
+
+    let token = token::create_scoped_token(
+        &user.id.to_string(),
+        SECRETS_READ_SCOPE,
+        None,
+        &app_state.signing_keys,
+        ttl_minutes
+    )
+    .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(ScopedTokenResponseDto {
+        status: "success".to_string(),
+        token,
+        scope: SECRETS_READ_SCOPE.to_string(),
+        expires_at: Utc::now() + Duration::minutes(ttl_minutes),
+    }))
+}
+
+pub async fn get_jwt_roles(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let roles = app_state.db_client
+        .get_jwt_auth_roles(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = JwtAuthRoleListResponseDto {
+        status: "success".to_string(),
+        roles: FilterJwtAuthRoleDto::filter_roles(&roles),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn create_jwt_role(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<JwtAuthRoleDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    if !app_state.env.workload_issuers.iter().any(|issuer| issuer.issuer == body.issuer) {
+        return Err(HttpError::bad_request("Issuer is not listed in JWT_AUTH_ISSUERS"));
+    }
+
+    let bound_claims: HashMap<String, Vec<String>> = body.bound_claims
+        .into_iter()
+        .map(|(name, value)| (name, value.into_values()))
+        .collect();
+
+    // Without bound claims any workload of the issuer that can mint a token
+    // for this audience would be let in.
+    if bound_claims.is_empty() || bound_claims.values().any(|values| values.is_empty()) {
+        return Err(HttpError::bad_request("At least one bound claim with a value is required"));
+    }
+
+    let token_ttl_minutes = body.token_ttl_minutes.unwrap_or(DEFAULT_TOKEN_TTL_MINUTES);
+
+    if i64::from(token_ttl_minutes) > app_state.env.jwt_maxage {
+        return Err(HttpError::bad_request(format!("Token TTL must not exceed {} minutes", app_state.env.jwt_maxage)));
+    }
+
+    let result = app_state.db_client
+        .save_jwt_auth_role(user.user.id, body.name, body.issuer, body.audience, bound_claims, token_ttl_minutes)
+        .await;
+
+    match result {
+        Ok(role) => Ok((StatusCode::CREATED, Json(JwtAuthRoleResponseDto {
+            status: "success".to_string(),
+            role: FilterJwtAuthRoleDto::filter_role(&role),
+        }))),
+        Err(sqlx::Error::Database(db_err)) => {
+            if db_err.is_unique_violation() {
+                Err(HttpError::unique_constraint_violation("A role with this name already exists"))
+            } else {
+                Err(HttpError::server_error(db_err.to_string()))
+            }
+        }
+        Err(e) => Err(HttpError::server_error(e.to_string()))
+    }
+}
+
+pub async fn delete_jwt_role(
+    Path(role_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let deleted = app_state.db_client
+        .delete_jwt_auth_role(user.user.id, role_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Err(HttpError::not_found("Role not found"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Role deleted successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
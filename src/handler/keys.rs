@@ -1,27 +1,75 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
-use axum::{extract::Query, response::IntoResponse, routing::get, Extension, Json, Router};
+use axum::{extract::{ConnectInfo, Query}, http::{HeaderMap, HeaderName, HeaderValue}, middleware, response::{IntoResponse, Response}, routing::get, Extension, Json, Router};
 
-use crate::{db::UserExt, dtos::{RequestQuerySecretByKeyDto, RequestQuerySecretByKeyResponseDto}, error::{ErrorMessage, HttpError}, secret::{PostgresSecretRespository, SecretRepository}, utils::{connect_user_database::connect_to_user_database, decrypt::decrypt}, AppState};
+use crate::{db::{ApiKeyExt, ClientCertificateExt, UserExt}, dtos::{RequestQuerySecretByKeyDto, RequestQuerySecretByKeyResponseDto}, error::{ErrorMessage, HttpError}, project::{PostgresProjectRepository, ProjectRepository}, secret::{PostgresSecretRespository, SecretRepository}, utils::{api_key, cidr, client_cert::PeerCertificate, connect_user_database::connect_to_user_database, decrypt::decrypt}, AppState};
 
 pub fn get_secret_key() -> Router {
     Router::new()
         .route("/secert", get(get_secret_by_key))
+        .layer(middleware::map_response(deprecation_headers))
+}
+
+// The query-string key ends up in access logs; clients should move to the
+// header-authenticated `/api/machine` routes.
+async fn deprecation_headers(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+
+    headers.insert(HeaderName::from_static("deprecation"), HeaderValue::from_static("true"));
+    headers.insert(
+        HeaderName::from_static("link"),
+        HeaderValue::from_static("</api/machine/secrets>; rel=\"successor-version\""),
+    );
+    headers.insert(
+        HeaderName::from_static("warning"),
+        HeaderValue::from_static("299 - \"Deprecated: send the API key in a header to /api/machine/secrets instead\""),
+    );
+
+    response
 }
 
 pub async fn get_secret_by_key(
     Query(query_params): Query<RequestQuerySecretByKeyDto>,
-    Extension(app_state): Extension<Arc<AppState>>
+    Extension(app_state): Extension<Arc<AppState>>,
+    ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
+    peer_certificate: Option<Extension<PeerCertificate>>,
 ) -> Result<impl IntoResponse, HttpError> {
-    let user_api_key = query_params.key;
-    let secret_id = query_params.secret;
-
-    let result = app_state.db_client
-        .get_user(None, None, None, Some(&user_api_key))
-        .await
-        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    let (result, api_key) = match (query_params.key, peer_certificate) {
+        (Some(user_api_key), _) => {
+            if !api_key::is_well_formed(&user_api_key) {
+                return Err(HttpError::unauthorized(ErrorMessage::InvalidApiKey.to_string()));
+            }
+
+            let api_key = app_state.db_client
+                .get_active_api_key(&api_key::prefix(&user_api_key), &api_key::hash(&app_state.env.api_key_secret, &user_api_key))
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+                .ok_or_else(|| HttpError::unauthorized(ErrorMessage::InvalidApiKey.to_string()))?;
+
+            let client_ip = cidr::client_ip(client_addr.ip(), &request_headers, &app_state.env.trusted_proxy_cidrs);
+            api_key::check_client_address(&app_state.api_key_usage, &api_key, client_ip)?;
+
+            app_state.api_key_usage.record(api_key.id, client_ip);
+
+            let result = app_state.db_client
+                .get_user(Some(api_key.user_id), None, None)
+                .await;
+
+            (result, Some(api_key))
+        }
+        (None, Some(Extension(peer_certificate))) => {
+            let result = app_state.db_client
+                .get_user_by_client_certificate(&peer_certificate.fingerprint)
+                .await;
+
+            (result, None)
+        }
+        (None, None) => return Err(HttpError::unauthorized(ErrorMessage::TokenNotProvided.to_string())),
+    };
 
     let user = result
+        .map_err(|e| HttpError::server_error(e.to_string()))?
         .ok_or_else(|| HttpError::unauthorized(ErrorMessage::UserNoLongerExist.to_string()))?;
 
     let user_db_connection = &user.db_connection.as_ref()
@@ -31,7 +79,35 @@ pub async fn get_secret_by_key(
 
     let repo = PostgresSecretRespository::new(&user_db_pool);
 
-    let secret = repo.get_secrets_by_id(secret_id).await?;
+    let environment_id = match &api_key {
+        Some(api_key) => api_key::scope_environment_id(api_key, query_params.environment_id)?,
+        None => query_params.environment_id,
+    };
+
+    let project_repo = PostgresProjectRepository::new(&user_db_pool);
+
+    let secret = match (query_params.secret, query_params.name) {
+        (Some(secret_id), _) => match &api_key {
+            Some(api_key) => api_key::secrets_by_ids(api_key, &repo, &[secret_id]).await?
+                .pop()
+                .ok_or_else(|| HttpError::not_found("Secret not found"))?,
+            None => repo.get_secrets_by_id(secret_id).await?,
+        },
+        (None, Some(secret_name)) => {
+            let environment = project_repo.resolve_environment(environment_id).await?;
+
+            repo.get_secrets_by_name(environment.id, &secret_name).await?
+        }
+        (None, None) => return Err(HttpError::bad_request("Provide the secret id or name")),
+    };
+
+    if let Some(api_key) = &api_key {
+        let environment_ids = api_key::readable_environment_ids(api_key, &project_repo).await?;
+
+        if !api_key.can_read(&secret, environment_ids.as_deref()) {
+            return Err(HttpError::forbidden(ErrorMessage::InsufficientScope.to_string()));
+        }
+    }
 
     let encryption_method = &user.encryption_method.as_ref()
         .ok_or_else(|| HttpError::server_error("Encryption Method Not Found"))?;
@@ -39,7 +115,7 @@ pub async fn get_secret_by_key(
     let encryption_key = &user.keys.as_ref()
         .ok_or_else(|| HttpError::server_error("Encryption Key Not Found"))?;
 
-    let decrypted_value_bytes = decrypt(&encryption_method, &encryption_key, &secret.encrypted_secret_value);
+    let decrypted_value_bytes = decrypt(encryption_method, encryption_key, &secret.encrypted_secret_value);
 
     let decrypted_value = String::from_utf8(decrypted_value_bytes)
             .map_err(|e| HttpError::server_error(e.to_string()))?;
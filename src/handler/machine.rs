@@ -0,0 +1,303 @@
+use axum::{extract::{Path, Query}, response::IntoResponse, routing::{get, post}, Extension, Json, Router};
+use validator::Validate;
+
+use crate::{dtos::{EnvironmentQueryDto, FilterSecretDto, FilterSecretMetadataDto, FolderQueryDto, FolderResponseDto, MachineSecretValueDto, RequestQueryDto, Response, SecretBatchDto, SecretBatchResponseDto, SecretMetadataResponseDto, SecretResponse, SingleSecretResponseDto}, error::{ErrorMessage, HttpError}, middleware::ApiKeyAuthMiddleware, models::{ApiKeyAccess, Secret, User}, project::{PostgresProjectRepository, ProjectRepository}, secret::{PostgresSecretRespository, SecretRepository}, utils::{api_key, connect_user_database::connect_to_user_database, decrypt::decrypt, encrypt::encrypt, secret_path}};
+
+pub fn machine_handler() -> Router {
+    Router::new()
+        .route("/secrets", get(get_secret_metadata))
+        .route("/secrets/batch", post(get_secrets_batch))
+        .route("/secrets/by-name/*name", get(get_secret_by_name))
+        .route("/folders", get(get_folder))
+        .route("/secrets/:id", get(get_secret_by_id).put(update_secret_value))
+}
+
+fn decrypt_secrets(user: &User, secrets: &[Secret]) -> Result<Vec<FilterSecretDto>, HttpError> {
+    let encryption_method = &user.encryption_method.as_ref()
+        .ok_or_else(|| HttpError::server_error("Encryption Method Not Found"))?;
+
+    let encryption_key = &user.keys.as_ref()
+        .ok_or_else(|| HttpError::server_error("Encryption Key Not Found"))?;
+
+    let mut send_secrets: Vec<SecretResponse> = Vec::new();
+
+    for secret in secrets {
+        let decrypted_value_bytes = decrypt(encryption_method, encryption_key, &secret.encrypted_secret_value);
+
+        let decrypted_value = String::from_utf8(decrypted_value_bytes)
+            .map_err(|e| HttpError::server_error(format!("Decryption failed: {}", e)))?;
+
+        send_secrets.push(
+            SecretResponse {
+                id: secret.id,
+                environment_id: Some(secret.environment_id),
+                inheritance: None,
+                secret_name: secret.secret_name.clone(),
+                secret_value: decrypted_value,
+                version: secret.version,
+                created_at: secret.created_at,
+                updated_at: secret.updated_at,
+                metadata: Some(secret.metadata.clone()),
+            }
+        );
+    }
+
+    Ok(FilterSecretDto::filter_secrets(&send_secrets))
+}
+
+pub async fn get_secret_metadata(
+    Query(query_params): Query<RequestQueryDto>,
+    Extension(auth): Extension<ApiKeyAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let environment_id = api_key::scope_environment_id(&auth.api_key, query_params.environment_id)?;
+
+    let db_connection = &auth.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let allowed_secrets = auth.api_key.allowed_secrets.as_deref();
+    let allowed_paths = auth.api_key.allowed_paths.as_deref();
+    let filter = query_params.secret_filter();
+
+    let response = match environment_id {
+        Some(environment_id) => {
+            let (total_count, secrets) = repo
+                .get_environment_secrets(environment_id, page as u32, limit as u32, allowed_secrets, allowed_paths, &filter)
+                .await?;
+
+            SecretMetadataResponseDto {
+                secret: FilterSecretMetadataDto::filter_inherited_secrets(&secrets),
+                total_count,
+            }
+        }
+        None => {
+            let (total_count, secrets) = repo
+                .get_secrets(page as u32, limit as u32, allowed_secrets, allowed_paths, &filter)
+                .await?;
+
+            SecretMetadataResponseDto {
+                secret: FilterSecretMetadataDto::filter_secrets(&secrets),
+                total_count,
+            }
+        }
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_folder(
+    Query(query_params): Query<FolderQueryDto>,
+    Extension(auth): Extension<ApiKeyAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let path = secret_path::normalize_folder(query_params.path.as_deref().unwrap_or_default());
+
+    let environment_id = api_key::scope_environment_id(&auth.api_key, query_params.environment_id)?;
+
+    let db_connection = &auth.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let environment = PostgresProjectRepository::new(&user_db_pool)
+        .resolve_environment(environment_id)
+        .await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let (folders, secrets) = repo
+        .get_folder(
+            environment.id,
+            &secret_path::folder_prefix(&path),
+            auth.api_key.allowed_secrets.as_deref(),
+            auth.api_key.allowed_paths.as_deref(),
+        )
+        .await?;
+
+    let response = FolderResponseDto {
+        environment_id: environment.id.to_string(),
+        path,
+        folders,
+        secret: FilterSecretMetadataDto::filter_inherited_secrets(&secrets),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_secret_by_id(
+    Path(secret_id): Path<uuid::Uuid>,
+    Extension(auth): Extension<ApiKeyAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let db_connection = &auth.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let environment_ids = api_key::readable_environment_ids(&auth.api_key, &PostgresProjectRepository::new(&user_db_pool)).await?;
+
+    let mut secrets = api_key::secrets_by_ids(&auth.api_key, &repo, &[secret_id]).await?;
+
+    secrets.retain(|secret| auth.api_key.can_read(secret, environment_ids.as_deref()));
+
+    let secret = decrypt_secrets(&auth.user, &secrets)?
+        .pop()
+        .ok_or_else(|| HttpError::not_found("Secret not found"))?;
+
+    Ok(Json(SingleSecretResponseDto { secret }))
+}
+
+// Only `ReadWrite` keys may write, and a scoped key only to secrets defined in
+// its own environment. A key that cannot even read the secret gets the same
+// 404 as on reads, so writes reveal nothing more than reads do.
+pub async fn update_secret_value(
+    Path(secret_id): Path<uuid::Uuid>,
+    Extension(auth): Extension<ApiKeyAuthMiddleware>,
+    Json(body): Json<MachineSecretValueDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let db_connection = &auth.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let environment_ids = api_key::readable_environment_ids(&auth.api_key, &PostgresProjectRepository::new(&user_db_pool)).await?;
+
+    let secret = api_key::secrets_by_ids(&auth.api_key, &repo, &[secret_id]).await?
+        .pop()
+        .filter(|secret| auth.api_key.can_read(secret, environment_ids.as_deref()))
+        .ok_or_else(|| HttpError::not_found("Secret not found"))?;
+
+    if auth.api_key.access != ApiKeyAccess::ReadWrite {
+        return Err(HttpError::forbidden(ErrorMessage::ApiKeyReadOnly.to_string()));
+    }
+
+    if !auth.api_key.can_write(&secret) {
+        return Err(HttpError::forbidden("The secret is inherited from another environment; this key can only change secrets defined in its own"));
+    }
+
+    let encryption_method = &auth.user.encryption_method.as_ref()
+        .ok_or_else(|| HttpError::server_error("Encryption Method Not Found"))?;
+
+    let encryption_key = &auth.user.keys.as_ref()
+        .ok_or_else(|| HttpError::server_error("Encryption Key Not Found"))?;
+
+    let encrypted_secret_value = encrypt(encryption_method, encryption_key, body.secret_value.as_bytes());
+
+    repo.edit_secrets(secret.id, secret.secret_name, encrypted_secret_value).await?;
+
+    let response = Response {
+        status: "success",
+        message: "Secret updated successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_secret_by_name(
+    Path(secret_name): Path<String>,
+    Query(query_params): Query<EnvironmentQueryDto>,
+    Extension(auth): Extension<ApiKeyAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let environment_id = api_key::scope_environment_id(&auth.api_key, query_params.environment_id)?;
+
+    let db_connection = &auth.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let project_repo = PostgresProjectRepository::new(&user_db_pool);
+
+    let environment = project_repo.resolve_environment(environment_id).await?;
+    let environment_ids = api_key::readable_environment_ids(&auth.api_key, &project_repo).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let secret = repo.get_secrets_by_name(environment.id, &secret_name).await?;
+
+    if !auth.api_key.can_read(&secret, environment_ids.as_deref()) {
+        return Err(HttpError::not_found("Secret not found"));
+    }
+
+    let secret = decrypt_secrets(&auth.user, &[secret])?
+        .pop()
+        .ok_or_else(|| HttpError::not_found("Secret not found"))?;
+
+    Ok(Json(SingleSecretResponseDto { secret }))
+}
+
+// Secrets the key may not read are reported as missing, so a batch reveals no
+// more than fetching each secret on its own would.
+pub async fn get_secrets_batch(
+    Extension(auth): Extension<ApiKeyAuthMiddleware>,
+    Json(body): Json<SecretBatchDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let ids = body.ids.unwrap_or_default();
+    let names = body.names.unwrap_or_default();
+
+    if ids.is_empty() && names.is_empty() {
+        return Err(HttpError::bad_request("Provide at least one id or name"));
+    }
+
+    let environment_id = api_key::scope_environment_id(&auth.api_key, body.environment_id)?;
+
+    let db_connection = &auth.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let project_repo = PostgresProjectRepository::new(&user_db_pool);
+
+    let environment_ids = api_key::readable_environment_ids(&auth.api_key, &project_repo).await?;
+
+    let mut secrets = api_key::secrets_by_ids(&auth.api_key, &repo, &ids).await?;
+
+    // Names are looked up in a single environment; ids can be from any the
+    // key may read, though a scoped key only sees what its environment sees.
+    if !names.is_empty() {
+        let environment = project_repo.resolve_environment(environment_id).await?;
+
+        for secret in repo.get_secrets_by_names(environment.id, &names).await? {
+            if !secrets.iter().any(|found| found.id == secret.id) {
+                secrets.push(secret);
+            }
+        }
+    }
+
+    secrets.retain(|secret| auth.api_key.can_read(secret, environment_ids.as_deref()));
+
+    let missing = ids.iter()
+        .filter(|id| !secrets.iter().any(|secret| secret.id == **id))
+        .map(|id| id.to_string())
+        .chain(
+            names.iter()
+                .filter(|name| !secrets.iter().any(|secret| secret.secret_name == **name))
+                .cloned()
+        )
+        .collect();
+
+    let response = SecretBatchResponseDto {
+        secret: decrypt_secrets(&auth.user, &secrets)?,
+        missing,
+    };
+
+    Ok(Json(response))
+}
@@ -3,4 +3,10 @@ pub mod user;
 pub mod setting;
 pub mod secrets;
 pub mod secrets_version;
-pub mod keys;
\ No newline at end of file
+pub mod keys;
+pub mod oidc;
+pub mod jwks;
+pub mod jwt_auth;
+pub mod approle;
+pub mod api_keys;
+pub mod machine;pub mod projects;
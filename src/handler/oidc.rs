@@ -0,0 +1,235 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{extract::{ConnectInfo, Query}, http::{header, HeaderMap}, response::{IntoResponse, Redirect}, routing::get, Extension, Json, Router};
+use axum_extra::extract::{cookie::SameSite, CookieJar};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::OidcConfig, db::{IdentityExt, UserExt}, dtos::{OidcCallbackQueryDto, OidcLinkResponseDto}, error::{ErrorMessage, HttpError}, handler::auth::login_response, middleware::JWTAuthMiddleware, utils::{cookie, oidc, password}, AppState};
+
+const FLOW_COOKIE: &str = "oidc_flow";
+const FLOW_MAXAGE_MINUTES: i64 = 10;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OidcFlowClaims {
+    state: String,
+    nonce: String,
+    code_verifier: String,
+    // Set when a signed-in user links their provider identity to their
+    // account, rather than signing in with it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    link_user_id: Option<uuid::Uuid>,
+    exp: usize,
+}
+
+pub fn oidc_handler() -> Router {
+    Router::new()
+        .route("/login", get(oidc_login))
+        .route("/callback", get(oidc_callback))
+}
+
+fn oidc_config(app_state: &AppState) -> Result<&OidcConfig, HttpError> {
+    app_state.env.oidc.as_ref()
+        .ok_or_else(|| HttpError::bad_request("OIDC login is not configured"))
+}
+
+// Starts an authorization-code flow and returns the provider URL to send the
+// browser to, together with the cookie carrying the flow.
+async fn start_flow(app_state: &AppState, link_user_id: Option<uuid::Uuid>) -> Result<(String, String), HttpError> {
+    let config = oidc_config(app_state)?;
+
+    let metadata = oidc::discover(&config.issuer_url).await?;
+
+    let flow = OidcFlowClaims {
+        state: oidc::random_token(32),
+        nonce: oidc::random_token(32),
+        code_verifier: oidc::random_token(64),
+        link_user_id,
+        exp: (Utc::now() + Duration::minutes(FLOW_MAXAGE_MINUTES)).timestamp() as usize,
+    };
+
+    let authorization_url = oidc::authorization_url(
+        config,
+        &metadata,
+        &flow.state,
+        &flow.nonce,
+        &oidc::pkce_challenge(&flow.code_verifier),
+    )?;
+
+    // The PKCE verifier, state and nonce travel in a signed, short-lived cookie
+    // so the callback can be handled by any instance without server-side storage.
+    let flow_token = encode(
+        &Header::default(),
+        &flow,
+        &EncodingKey::from_secret(app_state.env.jwt_secret.as_bytes()),
+    )
+    .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    // The callback arrives as a cross-site redirect from the provider, so the
+    // flow cookie must be sent even when COOKIE_SAME_SITE is Strict.
+    let mut flow_cookie = cookie::build(
+        &app_state.env.cookie,
+        FLOW_COOKIE,
+        flow_token,
+        time::Duration::minutes(FLOW_MAXAGE_MINUTES),
+        true,
+    );
+    flow_cookie.set_same_site(SameSite::Lax);
+
+    Ok((authorization_url, flow_cookie.to_string()))
+}
+
+pub async fn oidc_login(
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let (authorization_url, flow_cookie) = start_flow(&app_state, None).await?;
+
+    let mut response = Redirect::to(&authorization_url).into_response();
+    response.headers_mut().append(
+        header::SET_COOKIE,
+        flow_cookie.parse().unwrap(),
+    );
+
+    Ok(response)
+}
+
+// Links the provider identity to the signed-in account. This is a POST, so it
+// goes through the CSRF check, and returns the URL instead of redirecting; the
+// client sends the browser there and the callback completes the link.
+pub async fn link_oidc_identity(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let (authorization_url, flow_cookie) = start_flow(&app_state, Some(user.user.id)).await?;
+
+    let mut response = Json(OidcLinkResponseDto {
+        status: "success".to_string(),
+        authorization_url,
+    }).into_response();
+
+    response.headers_mut().append(
+        header::SET_COOKIE,
+        flow_cookie.parse().unwrap(),
+    );
+
+    Ok(response)
+}
+
+pub async fn oidc_callback(
+    cookie_jar: CookieJar,
+    Query(query_params): Query<OidcCallbackQueryDto>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
+    request_headers: HeaderMap,
+) -> Result<impl IntoResponse, HttpError> {
+    if let Some(error) = query_params.error {
+        return Err(HttpError::unauthorized(format!("OIDC login failed: {}", error)));
+    }
+
+    let config = oidc_config(&app_state)?;
+
+    let flow_token = cookie_jar
+        .get(FLOW_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .ok_or_else(|| HttpError::bad_request("OIDC login session not found or expired"))?;
+
+    let flow = decode::<OidcFlowClaims>(
+        &flow_token,
+        &DecodingKey::from_secret(app_state.env.jwt_secret.as_bytes()),
+        &Validation::new(Algorithm::HS256),
+    )
+    .map_err(|_| HttpError::bad_request("OIDC login session not found or expired"))?
+    .claims;
+
+    let (code, state) = match (query_params.code, query_params.state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => return Err(HttpError::bad_request("Missing authorization code or state")),
+    };
+
+    if state != flow.state {
+        return Err(HttpError::bad_request("OIDC state mismatch"));
+    }
+
+    let metadata = oidc::discover(&config.issuer_url).await?;
+
+    let token_response = oidc::exchange_code(config, &metadata, &code, &flow.code_verifier).await?;
+
+    let jwks = oidc::fetch_jwks(&metadata.jwks_uri).await?;
+
+    let claims = oidc::validate_id_token(
+        &token_response.id_token,
+        &jwks,
+        &metadata.issuer,
+        &config.client_id,
+        &flow.nonce,
+    )?;
+
+    if !claims.has_trusted_email(config) {
+        return Err(HttpError::unauthorized("Email address is not verified by the identity provider"));
+    }
+
+    let email = claims.email
+        .ok_or_else(|| HttpError::unauthorized("ID token does not contain an email address"))?;
+
+    let linked_user = app_state.db_client
+        .get_user_by_identity(&metadata.issuer, &claims.sub)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let user = match (linked_user, flow.link_user_id) {
+        (Some(user), Some(link_user_id)) if user.id != link_user_id => {
+            return Err(HttpError::unique_constraint_violation("This identity is already linked to another account"));
+        }
+        (Some(user), _) => user,
+        (None, Some(link_user_id)) => {
+            app_state.db_client
+                .save_identity(link_user_id, &metadata.issuer, &claims.sub)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+            app_state.db_client
+                .get_user(Some(link_user_id), None, None)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+                .ok_or_else(|| HttpError::unauthorized(ErrorMessage::UserNoLongerExist.to_string()))?
+        }
+        (None, None) => {
+            // Registration does not verify emails, so an account holding this
+            // email may have been registered by someone else. It can only be
+            // linked by its owner, while signed in.
+            let existing_user = app_state.db_client
+                .get_user(None, None, Some(&email))
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+            if existing_user.is_some() {
+                return Err(HttpError::unique_constraint_violation(ErrorMessage::IdentityNotLinked.to_string()));
+            }
+
+            // SSO-provisioned accounts get a random password nobody knows, so
+            // they can only sign in through the identity provider.
+            let hash_password = password::hash(&app_state.env.password_hashing, oidc::random_token(32))
+                .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+            let name = claims.name.unwrap_or_else(|| email.clone());
+
+            app_state.db_client
+                .save_user_with_identity(&name, &email, &hash_password, &metadata.issuer, &claims.sub)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+        }
+    };
+
+    let mut response = login_response(&app_state, &user, client_addr.ip(), &request_headers).await?;
+
+    let mut expired_flow_cookie = cookie::build(&app_state.env.cookie, FLOW_COOKIE, "", time::Duration::ZERO, true);
+    expired_flow_cookie.set_same_site(SameSite::Lax);
+
+    response.headers_mut().append(
+        header::SET_COOKIE,
+        expired_flow_cookie.to_string().parse().unwrap(),
+    );
+
+    Ok(response)
+}
@@ -0,0 +1,237 @@
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, routing::{get, put}, Extension, Json, Router};
+use sqlx::{Pool, Postgres};
+use validator::Validate;
+
+use crate::{dtos::{EnvironmentDto, EnvironmentListResponseDto, EnvironmentResponseDto, FilterEnvironmentDto, FilterProjectDto, ProjectDto, ProjectListResponseDto, ProjectResponseDto, Response}, error::HttpError, middleware::JWTAuthMiddleware, project::{PostgresProjectRepository, ProjectRepository}, utils::connect_user_database::connect_to_user_database};
+
+pub fn projects_handler() -> Router {
+    Router::new()
+        .route("/", get(get_projects).post(create_project))
+        .route("/:id", get(get_project).put(rename_project).delete(delete_project))
+        .route("/:id/environments", get(get_environments).post(create_environment))
+        .route("/:id/environments/:environment_id", put(update_environment).delete(delete_environment))
+}
+
+// A parent must belong to the same project, and an environment cannot inherit
+// from itself or from any environment that inherits from it.
+async fn check_parent(
+    repo: &PostgresProjectRepository<'_>,
+    project_id: uuid::Uuid,
+    environment_id: Option<uuid::Uuid>,
+    parent_id: Option<uuid::Uuid>,
+) -> Result<(), HttpError> {
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+
+    let parent = repo.get_environment(parent_id).await?;
+
+    if parent.project_id != project_id {
+        return Err(HttpError::bad_request("The parent environment must belong to the same project"));
+    }
+
+    if let Some(environment_id) = environment_id {
+        if repo.get_environment_chain(parent_id).await?.contains(&environment_id) {
+            return Err(HttpError::bad_request("An environment cannot inherit from itself or from an environment that inherits from it"));
+        }
+    }
+
+    Ok(())
+}
+
+async fn user_database(user: &JWTAuthMiddleware) -> Result<Pool<Postgres>, HttpError> {
+    let db_connection = &user.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    connect_to_user_database(db_connection).await
+}
+
+pub async fn get_projects(
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let user_db_pool = user_database(&user).await?;
+
+    let projects = PostgresProjectRepository::new(&user_db_pool)
+        .get_projects()
+        .await?;
+
+    let response = ProjectListResponseDto {
+        status: "success".to_string(),
+        projects: FilterProjectDto::filter_projects(&projects),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn create_project(
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<ProjectDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user_db_pool = user_database(&user).await?;
+
+    let project = PostgresProjectRepository::new(&user_db_pool)
+        .save_project(&body.name)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(ProjectResponseDto {
+        status: "success".to_string(),
+        project: FilterProjectDto::filter_project(&project),
+        environments: Vec::new(),
+    })))
+}
+
+pub async fn get_project(
+    Path(project_id): Path<uuid::Uuid>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let user_db_pool = user_database(&user).await?;
+
+    let repo = PostgresProjectRepository::new(&user_db_pool);
+
+    let project = repo.get_project(project_id).await?;
+    let environments = repo.get_environments(project_id).await?;
+
+    let response = ProjectResponseDto {
+        status: "success".to_string(),
+        project: FilterProjectDto::filter_project(&project),
+        environments: FilterEnvironmentDto::filter_environments(&environments),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn rename_project(
+    Path(project_id): Path<uuid::Uuid>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<ProjectDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user_db_pool = user_database(&user).await?;
+
+    let repo = PostgresProjectRepository::new(&user_db_pool);
+
+    let project = repo.rename_project(project_id, &body.name).await?;
+    let environments = repo.get_environments(project_id).await?;
+
+    let response = ProjectResponseDto {
+        status: "success".to_string(),
+        project: FilterProjectDto::filter_project(&project),
+        environments: FilterEnvironmentDto::filter_environments(&environments),
+    };
+
+    Ok(Json(response))
+}
+
+// Deletes the project and its environments; refused while any of them still
+// holds secrets, including secrets in the trash.
+pub async fn delete_project(
+    Path(project_id): Path<uuid::Uuid>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let user_db_pool = user_database(&user).await?;
+
+    PostgresProjectRepository::new(&user_db_pool)
+        .delete_project(project_id)
+        .await?;
+
+    let response = Response {
+        status: "success",
+        message: "Project deleted successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_environments(
+    Path(project_id): Path<uuid::Uuid>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let user_db_pool = user_database(&user).await?;
+
+    let repo = PostgresProjectRepository::new(&user_db_pool);
+
+    repo.get_project(project_id).await?;
+    let environments = repo.get_environments(project_id).await?;
+
+    let response = EnvironmentListResponseDto {
+        status: "success".to_string(),
+        environments: FilterEnvironmentDto::filter_environments(&environments),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn create_environment(
+    Path(project_id): Path<uuid::Uuid>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<EnvironmentDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user_db_pool = user_database(&user).await?;
+
+    let repo = PostgresProjectRepository::new(&user_db_pool);
+
+    check_parent(&repo, project_id, None, body.parent_id).await?;
+
+    let environment = repo
+        .save_environment(project_id, &body.name, body.parent_id)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(EnvironmentResponseDto {
+        status: "success".to_string(),
+        environment: FilterEnvironmentDto::filter_environment(&environment),
+    })))
+}
+
+// Sets the name and the parent; leaving out `parent_id` stops the environment
+// from inheriting.
+pub async fn update_environment(
+    Path((project_id, environment_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<EnvironmentDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user_db_pool = user_database(&user).await?;
+
+    let repo = PostgresProjectRepository::new(&user_db_pool);
+
+    check_parent(&repo, project_id, Some(environment_id), body.parent_id).await?;
+
+    let environment = repo
+        .update_environment(project_id, environment_id, &body.name, body.parent_id)
+        .await?;
+
+    let response = EnvironmentResponseDto {
+        status: "success".to_string(),
+        environment: FilterEnvironmentDto::filter_environment(&environment),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn delete_environment(
+    Path((project_id, environment_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let user_db_pool = user_database(&user).await?;
+
+    PostgresProjectRepository::new(&user_db_pool)
+        .delete_environment(project_id, environment_id)
+        .await?;
+
+    let response = Response {
+        status: "success",
+        message: "Environment deleted successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
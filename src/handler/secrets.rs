@@ -1,15 +1,17 @@
-use std::sync::Arc;
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, sync::Arc};
 
-use axum::{extract::Query, response::IntoResponse, routing::{get, post, put}, Extension, Json, Router};
+use axum::{extract::{Path, Query}, response::IntoResponse, routing::{delete, get, post, put}, Extension, Json, Router};
 use validator::Validate;
 
-use crate::{dtos::{EditSecretDto, FilterSecretDto, RequestQueryDto, Response, SaveSecretDto, SecretResponse, SecretResponseDto}, error::HttpError, middleware::JWTAuthMiddleware, secret::{PostgresSecretRespository, SecretRepository}, utils::{connect_user_database::connect_to_user_database, decrypt::decrypt, encrypt::encrypt}, AppState};
+use crate::{dtos::{EditSecretDto, EnvironmentQueryDto, FilterSecretDto, FilterSecretMetadataDto, FolderQueryDto, FolderResponseDto, MoveFolderDto, PromoteSecretsDto, PromoteSecretsResponseDto, PromotedSecretDto, RequestQueryDto, Response, SaveSecretDto, SecretDiffEntryDto, SecretDiffQueryDto, SecretDiffResponseDto, SecretDiffStatus, SecretMetadataDto, SecretMetadataResponseDto, SecretPromotion, SecretResponse, SecretResponseDto, SingleSecretResponseDto}, error::HttpError, middleware::JWTAuthMiddleware, models::{InheritedSecret, Secret, SecretInheritance, SecretMetadata, User}, project::{PostgresProjectRepository, ProjectRepository}, secret::{PostgresSecretRespository, SecretRepository}, utils::{connect_user_database::connect_to_user_database, decrypt::decrypt, encrypt::encrypt, fingerprint::fingerprint, secret_path}, AppState};
 
 #[derive(Debug)]
 pub struct SavedSecret {
+    pub environment_id: uuid::Uuid,
     pub secret_name: String,
     pub encrypted_secret_value: Vec<u8>,
     pub version: i32,
+    pub metadata: SecretMetadata,
 }
 
 pub fn secrets_handler() -> Router {
@@ -17,6 +19,16 @@ pub fn secrets_handler() -> Router {
         .route("/get", get(get_secrets))
         .route("/save", post(save_secrets))
         .route("/update", put(edit_secrets))
+        .route("/by-name/*name", get(get_secret_by_name))
+        .route("/folders", get(get_folder))
+        .route("/folders/move", put(move_folder))
+        .route("/diff", get(diff_secrets))
+        .route("/promote", post(promote_secrets))
+        .route("/trash", get(get_trash))
+        .route("/trash/:id", delete(purge_secret))
+        .route("/trash/:id/restore", post(restore_secret))
+        .route("/:id", delete(delete_secret))
+        .route("/:id/metadata", put(update_secret_metadata))
 }
 
 
@@ -28,8 +40,11 @@ pub async fn get_secrets(
     query_params.validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
+    let allowed_secrets = user.allowed_secrets.as_deref();
     let user = &user.user;
 
+    let filter = query_params.secret_filter();
+
     let page = query_params.page.unwrap_or(1);
     let limit = query_params.limit.unwrap_or(10);
 
@@ -40,7 +55,29 @@ pub async fn get_secrets(
 
     let repo = PostgresSecretRespository::new(&user_db_pool);
 
-    let (total_count, secrets) = repo.get_secrets(page as u32, limit as u32).await?;
+    // Listing one environment includes what it inherits and says where each
+    // value comes from.
+    let (total_count, secrets): (i64, Vec<(Secret, Option<SecretInheritance>)>) = match query_params.environment_id {
+        Some(environment_id) => {
+            let (total_count, secrets) = repo
+                .get_environment_secrets(environment_id, page as u32, limit as u32, allowed_secrets, None, &filter)
+                .await?;
+
+            let secrets = secrets.into_iter()
+                .map(|secret| {
+                    let inheritance = secret.inheritance();
+                    (secret.secret, Some(inheritance))
+                })
+                .collect();
+
+            (total_count, secrets)
+        }
+        None => {
+            let (total_count, secrets) = repo.get_secrets(page as u32, limit as u32, allowed_secrets, None, &filter).await?;
+
+            (total_count, secrets.into_iter().map(|secret| (secret, None)).collect())
+        }
+    };
 
     let encryption_method = &user.encryption_method.as_ref()
         .ok_or_else(|| HttpError::server_error("Encryption Method Not Found"))?;
@@ -50,8 +87,8 @@ pub async fn get_secrets(
 
     let mut send_secrets: Vec<SecretResponse> = Vec::new();
 
-    for secret in secrets {
-        let decrypted_value_bytes = decrypt(&encryption_method, &encryption_key, &secret.encrypted_secret_value);
+    for (secret, inheritance) in secrets {
+        let decrypted_value_bytes = decrypt(encryption_method, encryption_key, &secret.encrypted_secret_value);
 
         let decrypted_value = String::from_utf8(decrypted_value_bytes)
         .map_err(|e| HttpError::server_error(format!("Decryption failed: {}", e)))?;
@@ -59,11 +96,14 @@ pub async fn get_secrets(
         send_secrets.push(
             SecretResponse {
                 id: secret.id,
+                environment_id: Some(secret.environment_id),
+                inheritance,
                 secret_name: secret.secret_name.clone(),
                 secret_value: decrypted_value,
-                version: secret.version.clone(),
-                created_at: secret.created_at.clone(),
-                updated_at: secret.updated_at.clone(),
+                version: secret.version,
+                created_at: secret.created_at,
+                updated_at: secret.updated_at,
+                metadata: Some(secret.metadata),
             }
         );
     }
@@ -78,6 +118,349 @@ pub async fn get_secrets(
     Ok(Json(response))
 }
 
+pub async fn get_secret_by_name(
+    Path(secret_name): Path<String>,
+    Query(query_params): Query<EnvironmentQueryDto>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let db_connection = &user.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let environment = PostgresProjectRepository::new(&user_db_pool)
+        .resolve_environment(query_params.environment_id)
+        .await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let secret = repo.get_secrets_by_name(environment.id, &secret_name).await?;
+
+    if !user.can_read_secret(secret.id) {
+        return Err(HttpError::not_found("Secret not found"));
+    }
+
+    let encryption_method = &user.user.encryption_method.as_ref()
+        .ok_or_else(|| HttpError::server_error("Encryption Method Not Found"))?;
+
+    let encryption_key = &user.user.keys.as_ref()
+        .ok_or_else(|| HttpError::server_error("Encryption Key Not Found"))?;
+
+    let decrypted_value_bytes = decrypt(encryption_method, encryption_key, &secret.encrypted_secret_value);
+
+    let decrypted_value = String::from_utf8(decrypted_value_bytes)
+        .map_err(|e| HttpError::server_error(format!("Decryption failed: {}", e)))?;
+
+    let response = SingleSecretResponseDto {
+        secret: FilterSecretDto::filter_secret(&SecretResponse {
+            id: secret.id,
+            environment_id: Some(secret.environment_id),
+            inheritance: None,
+            secret_name: secret.secret_name,
+            secret_value: decrypted_value,
+            version: secret.version,
+            created_at: secret.created_at,
+            updated_at: secret.updated_at,
+            metadata: Some(secret.metadata),
+        }),
+    };
+
+    Ok(Json(response))
+}
+
+// Lists a folder's sub-folders and secrets without decrypting anything.
+pub async fn get_folder(
+    Query(query_params): Query<FolderQueryDto>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let path = secret_path::normalize_folder(query_params.path.as_deref().unwrap_or_default());
+
+    let db_connection = &user.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let environment = PostgresProjectRepository::new(&user_db_pool)
+        .resolve_environment(query_params.environment_id)
+        .await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let (folders, secrets) = repo
+        .get_folder(environment.id, &secret_path::folder_prefix(&path), user.allowed_secrets.as_deref(), None)
+        .await?;
+
+    let response = FolderResponseDto {
+        environment_id: environment.id.to_string(),
+        path,
+        folders,
+        secret: FilterSecretMetadataDto::filter_inherited_secrets(&secrets),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn move_folder(
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<MoveFolderDto>
+) -> Result<impl IntoResponse, HttpError> {
+    let from = secret_path::normalize_folder(&body.from);
+    let to = secret_path::normalize_folder(&body.to);
+
+    let body = MoveFolderDto { from, to, ..body };
+
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    if body.to == body.from || body.to.starts_with(&secret_path::folder_prefix(&body.from)) {
+        return Err(HttpError::bad_request("A folder cannot be moved into itself"));
+    }
+
+    let db_connection = &user.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let environment = PostgresProjectRepository::new(&user_db_pool)
+        .resolve_environment(body.environment_id)
+        .await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let moved = repo
+        .move_folder(environment.id, &secret_path::folder_prefix(&body.from), &secret_path::folder_prefix(&body.to))
+        .await?;
+
+    if moved == 0 {
+        return Err(HttpError::not_found("Folder not found"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: format!("Moved {} secrets to {}", moved, body.to),
+    };
+
+    Ok(Json(response))
+}
+
+fn parse_folder(path: Option<&str>) -> Result<String, HttpError> {
+    let path = secret_path::normalize_folder(path.unwrap_or_default());
+
+    if !path.is_empty() && !secret_path::is_valid(&path) {
+        return Err(HttpError::bad_request(format!("Invalid folder `{}`", path)));
+    }
+
+    Ok(path)
+}
+
+// Maps the path of each secret below `prefix` to the fingerprint of its value.
+fn fingerprints(user: &User, secrets: &[InheritedSecret], prefix: &str) -> Result<BTreeMap<String, String>, HttpError> {
+    let encryption_method = &user.encryption_method.as_ref()
+        .ok_or_else(|| HttpError::server_error("Encryption Method Not Found"))?;
+
+    let encryption_key = &user.keys.as_ref()
+        .ok_or_else(|| HttpError::server_error("Encryption Key Not Found"))?;
+
+    Ok(secrets
+        .iter()
+        .map(|secret| {
+            let value = decrypt(encryption_method, encryption_key, &secret.secret.encrypted_secret_value);
+
+            (secret.secret.secret_name[prefix.len()..].to_string(), fingerprint(encryption_key, &value))
+        })
+        .collect())
+}
+
+// Compares what two environments, or two folders, see by name and by value
+// fingerprint. Values themselves are never returned.
+pub async fn diff_secrets(
+    Query(query_params): Query<SecretDiffQueryDto>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let source_path = parse_folder(query_params.source_path.as_deref())?;
+    let target_path = parse_folder(query_params.target_path.as_deref())?;
+
+    let source_prefix = secret_path::folder_prefix(&source_path);
+    let target_prefix = secret_path::folder_prefix(&target_path);
+
+    let db_connection = &user.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let project_repo = PostgresProjectRepository::new(&user_db_pool);
+
+    let source_environment = project_repo.resolve_environment(query_params.source_environment_id).await?;
+    let target_environment = project_repo.resolve_environment(query_params.target_environment_id).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let mut source_secrets = repo.get_secrets_under(source_environment.id, &source_prefix).await?;
+    let mut target_secrets = repo.get_secrets_under(target_environment.id, &target_prefix).await?;
+
+    source_secrets.retain(|secret| user.can_read_secret(secret.secret.id));
+    target_secrets.retain(|secret| user.can_read_secret(secret.secret.id));
+
+    let source = fingerprints(&user.user, &source_secrets, &source_prefix)?;
+    let target = fingerprints(&user.user, &target_secrets, &target_prefix)?;
+
+    let names: BTreeSet<&String> = source.keys().chain(target.keys()).collect();
+
+    let entries = names
+        .into_iter()
+        .map(|name| {
+            let source_fingerprint = source.get(name).cloned();
+            let target_fingerprint = target.get(name).cloned();
+
+            let status = match (&source_fingerprint, &target_fingerprint) {
+                (Some(source), Some(target)) if source == target => SecretDiffStatus::Unchanged,
+                (Some(_), Some(_)) => SecretDiffStatus::Changed,
+                (Some(_), None) => SecretDiffStatus::OnlyInSource,
+                (None, _) => SecretDiffStatus::OnlyInTarget,
+            };
+
+            SecretDiffEntryDto {
+                name: name.to_owned(),
+                status,
+                source_fingerprint,
+                target_fingerprint,
+            }
+        })
+        .collect();
+
+    let response = SecretDiffResponseDto {
+        status: "success".to_string(),
+        source_environment_id: source_environment.id.to_string(),
+        source_path,
+        target_environment_id: target_environment.id.to_string(),
+        target_path,
+        entries,
+    };
+
+    Ok(Json(response))
+}
+
+// Copies the named secrets from the source to the target. A secret the target
+// already owns gets a new version through `edit_secrets`; one the target lacks,
+// or only inherits with a different value, is created there. Secrets whose
+// value already matches are left alone. All writes share one transaction, so a
+// failed promotion changes nothing.
+pub async fn promote_secrets(
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<PromoteSecretsDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let source_path = parse_folder(body.source_path.as_deref())?;
+    let target_path = parse_folder(body.target_path.as_deref())?;
+
+    if body.source_environment_id == body.target_environment_id && source_path == target_path {
+        return Err(HttpError::bad_request("Source and target are the same"));
+    }
+
+    let source_prefix = secret_path::folder_prefix(&source_path);
+    let target_prefix = secret_path::folder_prefix(&target_path);
+
+    let mut names = body.names;
+    names.sort();
+    names.dedup();
+
+    if let Some(invalid) = names.iter().find(|name| !secret_path::is_valid(&format!("{}{}", target_prefix, name))) {
+        return Err(HttpError::bad_request(format!("Invalid secret name `{}`", invalid)));
+    }
+
+    let user = &user.user;
+
+    let encryption_method = &user.encryption_method.as_ref()
+        .ok_or_else(|| HttpError::server_error("Encryption Method Not Found"))?;
+
+    let encryption_key = &user.keys.as_ref()
+        .ok_or_else(|| HttpError::server_error("Encryption Key Not Found"))?;
+
+    let db_connection = &user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let project_repo = PostgresProjectRepository::new(&user_db_pool);
+
+    let source_environment = project_repo.get_environment(body.source_environment_id).await?;
+    let target_environment = project_repo.get_environment(body.target_environment_id).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let by_path = |secrets: Vec<InheritedSecret>, prefix: &str| -> HashMap<String, InheritedSecret> {
+        secrets
+            .into_iter()
+            .map(|secret| (secret.secret.secret_name[prefix.len()..].to_string(), secret))
+            .collect()
+    };
+
+    let source = by_path(repo.get_secrets_under(source_environment.id, &source_prefix).await?, &source_prefix);
+    let target = by_path(repo.get_secrets_under(target_environment.id, &target_prefix).await?, &target_prefix);
+
+    let missing: Vec<&str> = names.iter()
+        .filter(|name| !source.contains_key(*name))
+        .map(|name| name.as_str())
+        .collect();
+
+    if !missing.is_empty() {
+        return Err(HttpError::not_found(format!("Not found in the source: {}", missing.join(", "))));
+    }
+
+    let mut promoted: Vec<PromotedSecretDto> = Vec::new();
+    let mut created: Vec<SavedSecret> = Vec::new();
+
+    let mut transaction = repo.begin().await?;
+
+    for name in names {
+        let source_secret = &source[&name].secret;
+        let source_value = decrypt(encryption_method, encryption_key, &source_secret.encrypted_secret_value);
+        let target_name = format!("{}{}", target_prefix, name);
+
+        let (result, version) = match target.get(&name) {
+            Some(existing) if decrypt(encryption_method, encryption_key, &existing.secret.encrypted_secret_value) == source_value => {
+                (SecretPromotion::Unchanged, existing.secret.version)
+            }
+            Some(existing) if existing.depth == 0 => {
+                repo.edit_secrets_in(&mut transaction, existing.secret.id, target_name, source_secret.encrypted_secret_value.clone()).await?;
+
+                (SecretPromotion::Updated, existing.secret.version + 1)
+            }
+            _ => {
+                created.push(SavedSecret {
+                    environment_id: target_environment.id,
+                    secret_name: target_name,
+                    encrypted_secret_value: source_secret.encrypted_secret_value.clone(),
+                    version: 1,
+                    metadata: source_secret.metadata.clone(),
+                });
+
+                (SecretPromotion::Created, 1)
+            }
+        };
+
+        promoted.push(PromotedSecretDto { name, result, version });
+    }
+
+    if !created.is_empty() {
+        repo.save_secrets_in(&mut transaction, created).await?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = PromoteSecretsResponseDto {
+        status: "success".to_string(),
+        secrets: promoted,
+    };
+
+    Ok(Json(response))
+}
+
 pub async fn save_secrets(
     Extension(_app_state): Extension<Arc<AppState>>,
     Extension(user): Extension<JWTAuthMiddleware>,
@@ -96,23 +479,39 @@ pub async fn save_secrets(
     let encryption_key = &user.keys.as_ref()
         .ok_or_else(|| HttpError::server_error("Encryption Key Not Found"))?;
 
+    let user_db_connection = &user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(user_db_connection).await?;
+
+    let project_repo = PostgresProjectRepository::new(&user_db_pool);
+
+    // Secrets that name no environment go to the default one.
+    let mut environments: HashMap<Option<uuid::Uuid>, uuid::Uuid> = HashMap::new();
+
     let mut saved_secrets: Vec<SavedSecret> = Vec::new();
 
     for dto in body {
-        let encrypted_secret_value = encrypt(&encryption_method, &encryption_key, &dto.secret_value.as_bytes());
+        let environment_id = match environments.get(&dto.environment_id) {
+            Some(environment_id) => *environment_id,
+            None => {
+                let environment = project_repo.resolve_environment(dto.environment_id).await?;
+                environments.insert(dto.environment_id, environment.id);
+                environment.id
+            }
+        };
+
+        let encrypted_secret_value = encrypt(encryption_method, encryption_key, dto.secret_value.as_bytes());
 
         saved_secrets.push(SavedSecret {
+            environment_id,
             secret_name: dto.secret_name.clone(),
             encrypted_secret_value,
             version: 1,
+            metadata: dto.metadata.into_metadata(),
         });
     }
 
-    let user_db_connection = &user.db_connection.as_ref()
-        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
-
-    let user_db_pool = connect_to_user_database(user_db_connection).await?;
-
     let repo = PostgresSecretRespository::new(&user_db_pool);
 
     repo.save_secrets(saved_secrets).await?;
@@ -142,7 +541,7 @@ pub async fn edit_secrets(
     let encryption_key = &user.keys.as_ref()
         .ok_or_else(|| HttpError::server_error("Encryption Key Not Found"))?;
 
-    let encrypted_secret_value = encrypt(&encryption_method, &encryption_key, &body.secret_value.as_bytes());
+    let encrypted_secret_value = encrypt(encryption_method, encryption_key, body.secret_value.as_bytes());
 
     let user_db_connection = &user.db_connection.as_ref()
         .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
@@ -151,6 +550,37 @@ pub async fn edit_secrets(
 
     let repo = PostgresSecretRespository::new(&user_db_pool);
 
+    // Editing an inherited secret from a child environment overrides it there
+    // and leaves the parent's value alone.
+    if let Some(environment_id) = body.environment_id {
+        let secret = repo.get_secrets_by_id(body.id).await?;
+
+        if secret.environment_id != environment_id {
+            let chain = PostgresProjectRepository::new(&user_db_pool)
+                .get_environment_chain(environment_id)
+                .await?;
+
+            if !chain.contains(&secret.environment_id) {
+                return Err(HttpError::bad_request("The secret is not in this environment or one it inherits from"));
+            }
+
+            repo.save_secrets(vec![SavedSecret {
+                environment_id,
+                secret_name: body.secret_name,
+                encrypted_secret_value,
+                version: 1,
+                metadata: secret.metadata,
+            }]).await?;
+
+            let response = Response {
+                status: "success",
+                message: "Secret overridden successfully".to_string(),
+            };
+
+            return Ok(Json(response));
+        }
+    }
+
     repo.edit_secrets(body.id, body.secret_name, encrypted_secret_value).await?;
 
     let response = Response {
@@ -159,4 +589,134 @@ pub async fn edit_secrets(
     };
 
     Ok(Json(response))
-}
\ No newline at end of file
+}
+
+// Replaces the description, owner, tags and labels of a secret without
+// touching its value or version.
+pub async fn update_secret_metadata(
+    Path(secret_id): Path<uuid::Uuid>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<SecretMetadataDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user_db_connection = &user.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(user_db_connection).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    if !repo.update_secret_metadata(secret_id, &body.into_metadata()).await? {
+        return Err(HttpError::not_found("Secret not found"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Secret metadata updated successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+// Moves the secret to the trash; it stops being readable anywhere until it is
+// restored.
+pub async fn delete_secret(
+    Path(secret_id): Path<uuid::Uuid>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let user_db_connection = &user.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(user_db_connection).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    if !repo.delete_secret(secret_id).await? {
+        return Err(HttpError::not_found("Secret not found"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Secret moved to trash".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_trash(
+    Query(query_params): Query<RequestQueryDto>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    query_params.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let page = query_params.page.unwrap_or(1);
+    let limit = query_params.limit.unwrap_or(10);
+
+    let db_connection = &user.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(db_connection).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    let (total_count, secrets) = repo
+        .get_deleted_secrets(page as u32, limit as u32, user.allowed_secrets.as_deref(), query_params.environment_id)
+        .await?;
+
+    let response = SecretMetadataResponseDto {
+        secret: FilterSecretMetadataDto::filter_secrets(&secrets),
+        total_count,
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn restore_secret(
+    Path(secret_id): Path<uuid::Uuid>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let user_db_connection = &user.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(user_db_connection).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    if !repo.restore_secret(secret_id).await? {
+        return Err(HttpError::not_found("Secret not found in trash"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Secret restored successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+// Permanently removes a trashed secret together with all of its versions.
+pub async fn purge_secret(
+    Path(secret_id): Path<uuid::Uuid>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let user_db_connection = &user.user.db_connection.as_ref()
+        .ok_or_else(|| HttpError::server_error("No Database connection found"))?;
+
+    let user_db_pool = connect_to_user_database(user_db_connection).await?;
+
+    let repo = PostgresSecretRespository::new(&user_db_pool);
+
+    if !repo.purge_secret(secret_id).await? {
+        return Err(HttpError::not_found("Secret not found in trash"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Secret permanently deleted".to_string(),
+    };
+
+    Ok(Json(response))
+}
@@ -18,6 +18,10 @@ pub async fn get_secret_version(
     query_params.validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
+    if !user.can_read_secret(query_params.id) {
+        return Err(HttpError::not_found("Secret not found"));
+    }
+
     let user = &user.user;
 
     let secret_id = query_params.id;
@@ -42,7 +46,7 @@ pub async fn get_secret_version(
     let mut send_secrets: Vec<SecretResponse> = Vec::new();
 
     for secret_version in secrets_version {
-        let decrypted_value_bytes = decrypt(&encryption_method, &encryption_key, &secret_version.encrypted_secret_value);
+        let decrypted_value_bytes = decrypt(encryption_method, encryption_key, &secret_version.encrypted_secret_value);
 
         let decrypted_value = String::from_utf8(decrypted_value_bytes)
             .map_err(|e| HttpError::server_error(e.to_string()))?;
@@ -50,11 +54,14 @@ pub async fn get_secret_version(
         send_secrets.push(
             SecretResponse {
                 id: secret_version.id,
+                environment_id: None,
+                inheritance: None,
                 secret_name: secret_version.secret_name.clone(),
                 secret_value: decrypted_value,
-                version: secret_version.version.clone(),
-                created_at: secret_version.created_at.clone(),
-                updated_at: secret_version.updated_at.clone(),
+                version: secret_version.version,
+                created_at: secret_version.created_at,
+                updated_at: secret_version.updated_at,
+                metadata: None,
             }
         );
     }
@@ -22,7 +22,7 @@ pub async fn database(
 
     let db_connection = DbConnection {
         host: body.host.clone(),
-        port: body.port.clone(),
+        port: body.port,
         username: body.username.clone(),
         password: body.password.clone(),
         database: body.database.clone(),
@@ -37,7 +37,7 @@ pub async fn database(
     let user_id = uuid::Uuid::parse_str(&user.user.id.to_string()).unwrap();
 
     app_state.db_client
-        .save_database_details(user_id.clone(), db_connection)
+        .save_database_details(user_id, db_connection)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -61,7 +61,7 @@ pub async fn encryption_method(
     let user_id = uuid::Uuid::parse_str(&user.user.id.to_string()).unwrap();
 
     app_state.db_client
-       .save_user_key(user_id.clone(), key, body.encryption_method)
+       .save_user_key(user_id, key, body.encryption_method)
        .await
        .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -1,34 +1,140 @@
 use std::sync::Arc;
 
-use axum::{response::IntoResponse, routing::{put, get}, Extension, Json, Router};
+use axum::{extract::Path, http::StatusCode, middleware, response::IntoResponse, routing::{delete, get, post, put}, Extension, Json, Router};
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
 use validator::Validate;
 
-use crate::{db::UserExt, dtos::{FilterUserDto, NameUpdateDto, Response, UserData, UserPasswordUpdateDto, UserResponseDto}, error::HttpError, middleware::JWTAuthMiddleware, utils::password, AppState};
+use crate::{db::{ApiKeyExt, ClientCertificateExt, IdentityExt, SessionExt, UserExt}, dtos::{ClientCertificateDto, ClientCertificateListResponseDto, ClientCertificateResponseDto, DeleteAccountDto, FilterClientCertificateDto, FilterSessionDto, FilterUserDto, NameUpdateDto, Response, SessionListResponseDto, UserData, UserPasswordUpdateDto, UserResponseDto}, error::HttpError, handler::{api_keys::api_keys_handler, auth::link_ldap_identity, approle::approles_handler, jwt_auth::jwt_roles_handler, oidc::link_oidc_identity}, middleware::{require_admin, JWTAuthMiddleware}, utils::{api_key, client_cert::PeerCertificate, connect_user_database::connect_to_user_database, create_table::drop_user_specific_table, password, password_policy::PASSWORD_POLICY}, AppState};
+
+const REAUTHENTICATION_MINUTES: i64 = 5;
 
 pub fn users_handler() -> Router {
     Router::new()
-    .route("/me", get(get_me))
+    .route("/me", get(get_me).delete(delete_me))
     .route("/name", put(update_user_name))
     .route("/password", put(update_user_password))
+    .route("/sessions", get(get_sessions))
+    .route("/sessions/:id", delete(revoke_session))
+    .route("/identities/oidc", post(link_oidc_identity))
+    .route("/identities/ldap", post(link_ldap_identity))
+    .merge(machine_credentials_handler())
+}
+
+fn machine_credentials_handler() -> Router {
+    Router::new()
+    .route("/certificates", get(get_certificates).post(add_certificate))
+    .route("/certificates/:id", delete(remove_certificate))
+    .nest("/jwt-roles", jwt_roles_handler())
+    .nest("/approles", approles_handler())
+    .nest("/api-keys", api_keys_handler())
+    .route_layer(middleware::from_fn(require_admin))
 }
 
 pub async fn get_me(
-    Extension(_app_state): Extension<Arc<AppState>>,
+    Extension(app_state): Extension<Arc<AppState>>,
     Extension(user): Extension<JWTAuthMiddleware>
 ) -> Result<impl IntoResponse, HttpError> {
 
     let filtered_user = FilterUserDto::filter_user(&user.user);
 
+    let api_keys = app_state.db_client
+        .get_api_keys(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .iter()
+        .filter(|api_key| api_key.revoked_at.is_none())
+        .map(|api_key| api_key::mask(&api_key.key_prefix))
+        .collect();
+
     let response_data = UserResponseDto {
         status: "success".to_string(),
         data: UserData {
             user: filtered_user,
+            api_keys: Some(api_keys),
         },
     };
 
     Ok(Json(response_data))
 }
 
+// SSO and LDAP accounts get a random local password they never learn, so
+// instead of the password they confirm a deletion by having signed in
+// recently.
+async fn check_recent_sign_in(app_state: &AppState, auth: &JWTAuthMiddleware) -> Result<(), HttpError> {
+    let has_identity = app_state.db_client
+        .has_identity(auth.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !has_identity {
+        return Err(HttpError::bad_request("Password is required."));
+    }
+
+    let session = match auth.session_id {
+        Some(session_id) => app_state.db_client
+            .get_session(session_id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?,
+        None => None,
+    };
+
+    let signed_in_recently = session
+        .is_some_and(|session| session.created_at > Utc::now() - Duration::minutes(REAUTHENTICATION_MINUTES));
+
+    if !signed_in_recently {
+        return Err(HttpError::unauthorized("Sign in again to confirm deleting the account"));
+    }
+
+    Ok(())
+}
+
+pub async fn delete_me(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    Json(body): Json<DeleteAccountDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    match &body.password {
+        Some(password) => {
+            let password_match = password::compare(&app_state.env.password_hashing, password, &user.user.password)
+                .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+            if !password_match {
+                return Err(HttpError::bad_request("Password is incorrect".to_string()));
+            }
+        }
+        None => check_recent_sign_in(&app_state, &user).await?,
+    }
+
+    let user = &user.user;
+
+    let secrets_dropped = match (&user.db_connection, body.drop_secrets) {
+        (Some(db_connection), true) => {
+            let user_db_pool = connect_to_user_database(db_connection).await?;
+            drop_user_specific_table(&user_db_pool).await?;
+            true
+        }
+        _ => false,
+    };
+
+    let email_hash = format!("{:x}", Sha256::digest(user.email.to_lowercase().as_bytes()));
+
+    app_state.db_client
+        .delete_user(user.id, email_hash, secrets_dropped)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = Response {
+        status: "success",
+        message: "Account deleted successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
 pub async fn update_user_name(
     Extension(app_state): Extension<Arc<AppState>>,
     Extension(user): Extension<JWTAuthMiddleware>,
@@ -40,7 +146,7 @@ pub async fn update_user_name(
     let user_id = uuid::Uuid::parse_str(&user.user.id.to_string()).unwrap();
 
     let result = app_state.db_client
-        .update_user_name(user_id.clone(), body.name)
+        .update_user_name(user_id, body.name)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -50,6 +156,7 @@ pub async fn update_user_name(
         status: "success".to_string(),
         data: UserData {
             user: filtered_user,
+            api_keys: None,
         },
     };
 
@@ -66,27 +173,168 @@ pub async fn update_user_password(
 
     let user = &user.user;
 
-    let password_match = password::compare(&body.old_password, &user.password)
+    let password_match = password::compare(&app_state.env.password_hashing, &body.old_password, &user.password)
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     if !password_match {
         return Err(HttpError::bad_request("Old password is incorrect".to_string()))?;
     }
 
-    let hash_password = password::hash(&body.new_password)
-        .map_err(|e| HttpError::server_error(e.to_string()))?;
-
     let user_id = uuid::Uuid::parse_str(&user.id.to_string()).unwrap();
 
+    let history_size = PASSWORD_POLICY.history_size;
+
+    if history_size > 0 {
+        let mut previous_passwords = app_state.db_client
+            .get_password_history(user_id, history_size)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        previous_passwords.push(user.password.clone());
+
+        for previous_password in previous_passwords {
+            if password::compare(&app_state.env.password_hashing, &body.new_password, &previous_password).unwrap_or(false) {
+                return Err(HttpError::bad_request(format!(
+                    "new_password: Password must not match any of your last {} passwords",
+                    history_size
+                )));
+            }
+        }
+    }
+
+    let hash_password = password::hash(&app_state.env.password_hashing, &body.new_password)
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
     app_state.db_client
-        .update_user_password(user_id.clone(), hash_password)
+        .update_user_password(user_id, hash_password.clone())
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    if history_size > 0 {
+        app_state.db_client
+            .save_password_history(user_id, hash_password, history_size)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+    }
+
     let response = Response {
         status: "success",
         message: "Password updated successfully".to_string(),
     };
 
     Ok(Json(response))
-}
\ No newline at end of file
+}
+
+pub async fn get_sessions(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let sessions = app_state.db_client
+        .get_user_sessions(user.user.id, app_state.env.jwt_maxage)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = SessionListResponseDto {
+        status: "success".to_string(),
+        sessions: FilterSessionDto::filter_sessions(&sessions, user.session_id),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn revoke_session(
+    Path(session_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let revoked = app_state.db_client
+        .revoke_session(user.user.id, session_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !revoked {
+        return Err(HttpError::not_found("Session not found"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Session revoked successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_certificates(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let certificates = app_state.db_client
+        .get_client_certificates(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = ClientCertificateListResponseDto {
+        status: "success".to_string(),
+        certificates: FilterClientCertificateDto::filter_certificates(&certificates),
+    };
+
+    Ok(Json(response))
+}
+
+// Binds the certificate presented on this request's TLS connection. The TLS
+// handshake proves the client holds its private key, which a pasted PEM,
+// fingerprint or subject would not.
+pub async fn add_certificate(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>,
+    peer_certificate: Option<Extension<PeerCertificate>>,
+    Json(body): Json<ClientCertificateDto>
+) -> Result<impl IntoResponse, HttpError> {
+    body.validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let Some(Extension(peer_certificate)) = peer_certificate else {
+        return Err(HttpError::bad_request("Connect with the client certificate to bind it"));
+    };
+
+    let result = app_state.db_client
+        .save_client_certificate(user.user.id, body.name, peer_certificate.fingerprint, peer_certificate.subject)
+        .await;
+
+    match result {
+        Ok(certificate) => Ok((StatusCode::CREATED, Json(ClientCertificateResponseDto {
+            status: "success".to_string(),
+            certificate: FilterClientCertificateDto::filter_certificate(&certificate),
+        }))),
+        Err(sqlx::Error::Database(db_err)) => {
+            if db_err.is_unique_violation() {
+                Err(HttpError::unique_constraint_violation("Certificate is already bound to a user"))
+            } else {
+                Err(HttpError::server_error(db_err.to_string()))
+            }
+        }
+        Err(e) => Err(HttpError::server_error(e.to_string()))
+    }
+}
+
+pub async fn remove_certificate(
+    Path(certificate_id): Path<uuid::Uuid>,
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JWTAuthMiddleware>
+) -> Result<impl IntoResponse, HttpError> {
+    let deleted = app_state.db_client
+        .delete_client_certificate(user.user.id, certificate_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Err(HttpError::not_found("Certificate not found"));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Certificate removed successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
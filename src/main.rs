@@ -5,13 +5,15 @@ mod dtos;
 mod db;
 mod utils;
 mod secret;
+mod project;
 mod middleware;
 mod handler;
 mod routes;
+mod tls;
 
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
-use axum::http::{header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE}, HeaderValue, Method};
+use axum::http::{header::{HeaderName, ACCEPT, AUTHORIZATION, CONTENT_TYPE}, HeaderValue, Method};
 use config::Config;
 use db::DBClient;
 use dotenv::dotenv;
@@ -19,12 +21,15 @@ use routes::create_router;
 use sqlx::postgres::PgPoolOptions;
 use tower_http::cors::CorsLayer;
 use tracing_subscriber::filter::LevelFilter;
+use utils::{api_key::API_KEY_HEADER, api_key_usage::UsageTracker, cookie::CSRF_HEADER, password_policy::PASSWORD_POLICY, signing_keys::SigningKeySet};
 
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub env: Config,
-    pub db_client: DBClient
+    pub db_client: DBClient,
+    pub signing_keys: Arc<SigningKeySet>,
+    pub api_key_usage: UsageTracker,
 }
 
 #[tokio::main]
@@ -34,6 +39,7 @@ async fn main() {
 
     dotenv().ok();
     let config = Config::init();
+    lazy_static::initialize(&PASSWORD_POLICY);
 
     let pool = match PgPoolOptions::new()
         .max_connections(10)
@@ -52,28 +58,55 @@ async fn main() {
 
     let cors = CorsLayer::new()
         .allow_origin("http://localhost:3000".parse::<HeaderValue>().unwrap())
-        .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE])
+        .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE, HeaderName::from_static(CSRF_HEADER), HeaderName::from_static(API_KEY_HEADER)])
         .allow_credentials(true)
-        .allow_methods([Method::GET, Method::POST, Method::PUT]);
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE]);
 
     let db_client = DBClient::new(pool);
 
+    match utils::api_key::hash_plaintext_keys(&db_client, &config.api_key_secret).await {
+        Ok(0) => {}
+        Ok(count) => println!("🔑 Hashed {} plaintext API keys", count),
+        Err(err) => {
+            println!("🔥 Failed to hash plaintext API keys: {:?}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let signing_keys = SigningKeySet::load(
+        config.jwt_signing_keys_file.as_deref(),
+        &config.jwt_secret,
+        config.jwt_maxage,
+    );
+
+    let api_key_usage = UsageTracker::start(db_client.clone());
+
     let app_state = AppState {
         env: config.clone(),
-        db_client
+        db_client,
+        signing_keys: Arc::new(signing_keys),
+        api_key_usage,
     };
 
     let app = create_router(Arc::new(app_state.clone())).layer(cors.clone());
 
-    println!(
-        "{}",
-        format!("🚀 Server is running on http://localhost:{}", config.port)
-    );
-
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", &config.port))
     .await
     .unwrap();
 
-    axum::serve(listener, app).await.unwrap();
+    match &config.tls {
+        Some(tls_config) => {
+            let server_config = tls::server_config(tls_config);
+
+            println!("🚀 Server is running on https://localhost:{}", config.port);
+
+            tls::serve(listener, app, server_config).await;
+        }
+        None => {
+            println!("🚀 Server is running on http://localhost:{}", config.port);
+
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+        }
+    }
 
 }
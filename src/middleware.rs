@@ -1,16 +1,33 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
-use axum::{extract::Request, http::header, middleware::Next, response::IntoResponse, Extension};
+use axum::{extract::{ConnectInfo, Request}, http::{header, HeaderMap, Method}, middleware::Next, response::IntoResponse, Extension};
 use axum_extra::extract::CookieJar;
 use serde::{Deserialize, Serialize};
 
-use crate::{db::UserExt, error::{ErrorMessage, HttpError}, models::User, utils::token, AppState};
+use crate::{db::{ApiKeyExt, AppRoleExt, ClientCertificateExt, SessionExt, UserExt}, error::{ErrorMessage, HttpError}, models::{ApiKey, User, UserRole}, utils::{api_key, cidr, client_cert::PeerCertificate, cookie::{CSRF_COOKIE, CSRF_HEADER, TOKEN_COOKIE}, token}, AppState};
 
 
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct JWTAuthMiddleware {
     pub user: User,
+    pub session_id: Option<uuid::Uuid>,
+    pub scope: Option<String>,
+    pub allowed_secrets: Option<Vec<uuid::Uuid>>,
+}
+
+impl JWTAuthMiddleware {
+    pub fn can_read_secret(&self, secret_id: uuid::Uuid) -> bool {
+        self.allowed_secrets
+            .as_ref()
+            .is_none_or(|allowed_secrets| allowed_secrets.contains(&secret_id))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKeyAuthMiddleware {
+    pub user: User,
+    pub api_key: ApiKey,
 }
 
 pub async fn auth(
@@ -19,40 +36,125 @@ pub async fn auth(
     mut req: Request,
     next: Next
 ) -> Result<impl IntoResponse, HttpError> {
-    let cookies = cookie_jar
-        .get("token")
-        .map(|cookie| cookie.value().to_string())
-        .or_else(|| {
-            req.headers()
-                .get(header::AUTHORIZATION)
-                .and_then(|auth_header| auth_header.to_str().ok())
-                .and_then(|auth_value| {
-                    if auth_value.starts_with("Bearer ") {
-                        Some(auth_value[7..].to_owned())
-                    } else  {
-                        None
-                    }
-                })
-        });
-
-    let token = cookies.ok_or_else(|| {
-        HttpError::unauthorized(ErrorMessage::TokenNotProvided.to_string())
-    })?;
+    // Credentials are tried in a fixed order: an `Authorization: Bearer`
+    // header, then the `token` cookie, then a bound client certificate.
+    // Browsers attach cookies and certificates to cross-site requests on
+    // their own, so state-changing requests authenticated by either must echo
+    // the CSRF cookie in a header. A Bearer header is never sent implicitly,
+    // so it wins over a cookie on the same request and needs no CSRF check.
+    let bearer_token = req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|auth_header| auth_header.to_str().ok())
+        .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
+        .map(|token| token.to_owned());
+
+    let cookie_token = cookie_jar
+        .get(TOKEN_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+
+    let token = match (bearer_token, cookie_token) {
+        (Some(token), _) => token,
+        (None, Some(token)) => {
+            if !is_safe_method(req.method()) {
+                verify_csrf_token(&cookie_jar, req.headers())?;
+            }
+
+            token
+        }
+        (None, None) => {
+            let peer_certificate = req.extensions().get::<PeerCertificate>().cloned()
+                .ok_or_else(|| HttpError::unauthorized(ErrorMessage::TokenNotProvided.to_string()))?;
+
+            if !is_safe_method(req.method()) {
+                verify_csrf_token(&cookie_jar, req.headers())?;
+            }
+
+            let user = app_state.db_client
+                .get_user_by_client_certificate(&peer_certificate.fingerprint)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+                .ok_or_else(|| HttpError::unauthorized(ErrorMessage::CertificateNotBound.to_string()))?;
+
+            req.extensions_mut().insert(JWTAuthMiddleware {
+                user,
+                session_id: None,
+                scope: None,
+                allowed_secrets: None,
+            });
+
+            return Ok(next.run(req).await);
+        }
+    };
 
-    let token_details = match token::decode_token(token, app_state.env.jwt_secret.as_bytes()) {
+    let token_details = match token::decode_token(token, &app_state.signing_keys) {
         Ok(token_details) => token_details,
         Err(_) => {
             return Err(HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()));
         }
     };
 
-    let user_id = uuid::Uuid::parse_str(&token_details.to_string())
+    let user_id = uuid::Uuid::parse_str(&token_details.sub)
         .map_err(|_| {
             HttpError::unauthorized(ErrorMessage::InvalidToken.to_string())
         })?;
 
+    // Scoped tokens are short-lived and not backed by a session; they may only
+    // read, and `require_full_access` keeps them off account routes.
+    let session_id = match &token_details.scope {
+        Some(_) => {
+            if !is_safe_method(req.method()) {
+                return Err(HttpError::forbidden(ErrorMessage::InsufficientScope.to_string()));
+            }
+
+            None
+        }
+        None => {
+            let session_id = token_details.sid.as_deref()
+                .and_then(|sid| uuid::Uuid::parse_str(sid).ok())
+                .ok_or_else(|| {
+                    HttpError::unauthorized(ErrorMessage::InvalidToken.to_string())
+                })?;
+
+            let session = app_state.db_client
+                    .get_session(session_id)
+                    .await
+                    .map_err(|e| HttpError::server_error(e.to_string()))?
+                    .filter(|session| session.user_id == user_id)
+                    .ok_or_else(|| HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
+
+            if session.revoked_at.is_some() {
+                return Err(HttpError::unauthorized(ErrorMessage::SessionRevoked.to_string()));
+            }
+
+            app_state.db_client
+                .touch_session(session_id)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+            Some(session_id)
+        }
+    };
+
+    // AppRole tokens are checked against their role on every request, so
+    // deleting the role revokes them straight away.
+    let allowed_secrets = match token_details.approle.as_deref() {
+        Some(approle_id) => {
+            let approle_id = uuid::Uuid::parse_str(approle_id)
+                .map_err(|_| HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
+
+            app_state.db_client
+                .get_approle(approle_id)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?
+                .filter(|approle| approle.user_id == user_id)
+                .ok_or_else(|| HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?
+                .allowed_secrets
+        }
+        None => None,
+    };
+
     let user = app_state.db_client
-            .get_user(Some(user_id.clone()), None, None, None)
+            .get_user(Some(user_id), None, None)
             .await
             .map_err(|_| HttpError::unauthorized(ErrorMessage::UserNoLongerExist.to_string()))?;
 
@@ -62,7 +164,91 @@ pub async fn auth(
 
     req.extensions_mut().insert(JWTAuthMiddleware {
         user: user.clone(),
+        session_id,
+        scope: token_details.scope,
+        allowed_secrets,
     });
 
     Ok(next.run(req).await)
-}
\ No newline at end of file
+}
+
+pub async fn require_full_access(
+    Extension(auth): Extension<JWTAuthMiddleware>,
+    req: Request,
+    next: Next
+) -> Result<impl IntoResponse, HttpError> {
+    if auth.scope.is_some() {
+        return Err(HttpError::forbidden(ErrorMessage::InsufficientScope.to_string()));
+    }
+
+    Ok(next.run(req).await)
+}
+
+// Machine credentials keep working after the user's session ends, so only
+// admins may manage them. Directory users are admins only when one of their
+// groups maps to `Admin` in LDAP_GROUP_ROLES.
+pub async fn require_admin(
+    Extension(auth): Extension<JWTAuthMiddleware>,
+    req: Request,
+    next: Next
+) -> Result<impl IntoResponse, HttpError> {
+    if auth.user.role != UserRole::Admin {
+        return Err(HttpError::forbidden(ErrorMessage::AdminRequired.to_string()));
+    }
+
+    Ok(next.run(req).await)
+}
+
+// Authenticates machine clients by API key sent in a header, never in the URL,
+// so keys stay out of access logs and proxy caches.
+pub async fn api_key_auth(
+    Extension(app_state): Extension<Arc<AppState>>,
+    ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
+    mut req: Request,
+    next: Next
+) -> Result<impl IntoResponse, HttpError> {
+    let key = api_key::from_headers(req.headers())
+        .ok_or_else(|| HttpError::unauthorized(ErrorMessage::ApiKeyNotProvided.to_string()))?;
+
+    if !api_key::is_well_formed(&key) {
+        return Err(HttpError::unauthorized(ErrorMessage::InvalidApiKey.to_string()));
+    }
+
+    let api_key = app_state.db_client
+        .get_active_api_key(&api_key::prefix(&key), &api_key::hash(&app_state.env.api_key_secret, &key))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::unauthorized(ErrorMessage::InvalidApiKey.to_string()))?;
+
+    let client_ip = cidr::client_ip(client_addr.ip(), req.headers(), &app_state.env.trusted_proxy_cidrs);
+    api_key::check_client_address(&app_state.api_key_usage, &api_key, client_ip)?;
+
+    app_state.api_key_usage.record(api_key.id, client_ip);
+
+    let user = app_state.db_client
+        .get_user(Some(api_key.user_id), None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::unauthorized(ErrorMessage::UserNoLongerExist.to_string()))?;
+
+    req.extensions_mut().insert(ApiKeyAuthMiddleware {
+        user,
+        api_key,
+    });
+
+    Ok(next.run(req).await)
+}
+
+fn is_safe_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+fn verify_csrf_token(cookie_jar: &CookieJar, headers: &HeaderMap) -> Result<(), HttpError> {
+    let csrf_cookie = cookie_jar.get(CSRF_COOKIE).map(|cookie| cookie.value());
+    let csrf_header = headers.get(CSRF_HEADER).and_then(|value| value.to_str().ok());
+
+    match (csrf_cookie, csrf_header) {
+        (Some(cookie), Some(header)) if !cookie.is_empty() && cookie == header => Ok(()),
+        _ => Err(HttpError::forbidden(ErrorMessage::CsrfTokenMismatch.to_string())),
+    }
+}
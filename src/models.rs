@@ -1,7 +1,11 @@
+use std::{collections::{BTreeMap, HashMap}, net::IpAddr};
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::types::Json;
 
+use crate::utils::{cidr, secret_path};
+
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, sqlx::Type, PartialEq)]
 #[sqlx(type_name = "encryption_method")]
@@ -12,6 +16,20 @@ pub enum EncryptionMethod {
     DESTriphleDES,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, sqlx::Type, PartialEq, PartialOrd)]
+#[sqlx(type_name = "user_role")]
+pub enum UserRole {
+    User,
+    Admin,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, sqlx::Type, PartialEq)]
+#[sqlx(type_name = "api_key_access")]
+pub enum ApiKeyAccess {
+    Read,
+    ReadWrite,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, sqlx::Type)]
 pub struct DbConnection {
     pub host: String,
@@ -29,8 +47,8 @@ pub struct User {
     pub password: String,
     pub encryption_method: Option<EncryptionMethod>,
     pub keys: Option<Vec<u8>>,
-    pub api_keys: Option<String>,
     pub db_connection: Option<Json<DbConnection>>,
+    pub role: UserRole,
     pub created_at: Option<DateTime<Utc>>, 
     pub updated_at: Option<DateTime<Utc>>, 
 }
@@ -39,11 +57,73 @@ pub struct User {
 #[derive(Debug, sqlx::FromRow, Serialize, Deserialize, sqlx::Type, Clone)]
 pub struct Secret {
     pub id: uuid::Uuid,
+    pub environment_id: uuid::Uuid,
     pub secret_name: String,
     pub encrypted_secret_value: Vec<u8>,
     pub version: i32,
     pub created_at: DateTime<Utc>, 
     pub updated_at: DateTime<Utc>, 
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[sqlx(flatten)]
+    pub metadata: SecretMetadata,
+}
+
+// Descriptive fields kept in plain text next to the ciphertext. They are not
+// versioned, so changing them leaves the secret's version alone.
+#[derive(Debug, Default, sqlx::FromRow, Serialize, Deserialize, sqlx::Type, Clone)]
+pub struct SecretMetadata {
+    pub description: Option<String>,
+    pub owner: Option<String>,
+    pub tags: Vec<String>,
+    pub labels: Json<BTreeMap<String, String>>,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct Project {
+    pub id: uuid::Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct Environment {
+    pub id: uuid::Uuid,
+    pub project_id: uuid::Uuid,
+    pub parent_id: Option<uuid::Uuid>,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretInheritance {
+    Own,
+    Inherited,
+    Override,
+}
+
+// A secret as seen from an environment: `depth` is how many parents up it was
+// found, and `definitions` how many environments in the chain define the name.
+#[derive(Debug, sqlx::FromRow, Clone)]
+pub struct InheritedSecret {
+    #[sqlx(flatten)]
+    pub secret: Secret,
+    pub depth: i32,
+    pub definitions: i64,
+}
+
+impl InheritedSecret {
+    pub fn inheritance(&self) -> SecretInheritance {
+        if self.depth > 0 {
+            SecretInheritance::Inherited
+        } else if self.definitions > 1 {
+            SecretInheritance::Override
+        } else {
+            SecretInheritance::Own
+        }
+    }
 }
 
 #[derive(Debug, sqlx::FromRow, Serialize, Deserialize, sqlx::Type, Clone)]
@@ -55,4 +135,219 @@ pub struct SecretVersion {
     pub version: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct Session {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct ClientCertificate {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    pub fingerprint: String,
+    pub subject: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct JwtAuthRole {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    pub issuer: String,
+    pub audience: String,
+    pub bound_claims: Json<HashMap<String, Vec<String>>>,
+    pub token_ttl_minutes: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct AppRole {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    pub allowed_secrets: Option<Vec<uuid::Uuid>>,
+    pub token_ttl_minutes: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct AppRoleSecretId {
+    pub id: uuid::Uuid,
+    pub approle_id: uuid::Uuid,
+    pub uses_remaining: Option<i32>,
+    pub bound_cidrs: Option<Vec<String>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize, Clone)]
+pub struct ApiKey {
+    pub id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub name: String,
+    pub key_prefix: String,
+    pub access: ApiKeyAccess,
+    pub allowed_secrets: Option<Vec<uuid::Uuid>>,
+    pub allowed_paths: Option<Vec<String>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub replaced_by: Option<uuid::Uuid>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub allowed_cidrs: Option<Vec<String>>,
+    pub last_used_ip: Option<String>,
+    pub request_count: i64,
+    pub environment_id: Option<uuid::Uuid>,
+}
+
+impl ApiKey {
+    // A key limited by `allowed_secrets`, `allowed_paths` or both reads the
+    // secrets either of them admits; a key with neither reads every secret.
+    pub fn can_read_secret(&self, secret: &Secret) -> bool {
+        if self.allowed_secrets.is_none() && self.allowed_paths.is_none() {
+            return true;
+        }
+
+        self.allowed_secrets
+            .as_ref()
+            .is_some_and(|allowed_secrets| allowed_secrets.contains(&secret.id))
+            || self.allowed_paths
+                .as_ref()
+                .is_some_and(|allowed_paths| allowed_paths.iter().any(|pattern| secret_path::matches(pattern, &secret.secret_name)))
+    }
+
+    // `environment_ids` are the environments a scoped key reads from: its own
+    // and the ones it inherits from. Unscoped keys pass `None`.
+    pub fn can_read(&self, secret: &Secret, environment_ids: Option<&[uuid::Uuid]>) -> bool {
+        self.can_read_secret(secret)
+            && environment_ids.is_none_or(|environment_ids| environment_ids.contains(&secret.environment_id))
+    }
+
+    // A scoped key reads through inheritance but only writes to its own
+    // environment: changing a parent's secret would change it for every
+    // environment inheriting from that parent.
+    pub fn can_write(&self, secret: &Secret) -> bool {
+        self.access == ApiKeyAccess::ReadWrite
+            && self.can_read_secret(secret)
+            && self.environment_id.is_none_or(|environment_id| environment_id == secret.environment_id)
+    }
+
+    pub fn allows_address(&self, ip: IpAddr) -> bool {
+        self.allowed_cidrs
+            .as_ref()
+            .is_none_or(|allowed_cidrs| cidr::contains(allowed_cidrs, ip))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
+pub struct ApiKeyRejection {
+    pub id: uuid::Uuid,
+    pub api_key_id: uuid::Uuid,
+    pub ip_address: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_key(access: ApiKeyAccess, environment_id: Option<uuid::Uuid>) -> ApiKey {
+        ApiKey {
+            id: uuid::Uuid::new_v4(),
+            user_id: uuid::Uuid::new_v4(),
+            name: "deploy".to_string(),
+            key_prefix: "sbk_live_AAAAAAAA".to_string(),
+            access,
+            allowed_secrets: None,
+            allowed_paths: None,
+            expires_at: None,
+            created_at: Utc::now(),
+            last_used_at: None,
+            revoked_at: None,
+            replaced_by: None,
+            rotated_at: None,
+            allowed_cidrs: None,
+            last_used_ip: None,
+            request_count: 0,
+            environment_id,
+        }
+    }
+
+    fn secret(environment_id: uuid::Uuid) -> Secret {
+        Secret {
+            id: uuid::Uuid::new_v4(),
+            environment_id,
+            secret_name: "db/password".to_string(),
+            encrypted_secret_value: Vec::new(),
+            version: 1,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            deleted_at: None,
+            metadata: SecretMetadata::default(),
+        }
+    }
+
+    #[test]
+    fn read_keys_cannot_write() {
+        let environment_id = uuid::Uuid::new_v4();
+
+        assert!(!api_key(ApiKeyAccess::Read, None).can_write(&secret(environment_id)));
+        assert!(!api_key(ApiKeyAccess::Read, Some(environment_id)).can_write(&secret(environment_id)));
+    }
+
+    #[test]
+    fn scoped_keys_write_only_to_their_own_environment() {
+        let parent = uuid::Uuid::new_v4();
+        let child = uuid::Uuid::new_v4();
+        let key = api_key(ApiKeyAccess::ReadWrite, Some(child));
+        let inherited = secret(parent);
+
+        assert!(key.can_read(&inherited, Some(&[child, parent])));
+        assert!(!key.can_write(&inherited));
+        assert!(key.can_write(&secret(child)));
+    }
+
+    #[test]
+    fn unscoped_keys_write_anywhere_they_may_read() {
+        let mut key = api_key(ApiKeyAccess::ReadWrite, None);
+        let allowed = secret(uuid::Uuid::new_v4());
+
+        assert!(key.can_write(&allowed));
+
+        key.allowed_secrets = Some(vec![allowed.id]);
+
+        assert!(key.can_write(&allowed));
+        assert!(!key.can_write(&secret(allowed.environment_id)));
+    }
+
+    #[test]
+    fn path_scopes_add_to_secret_scopes() {
+        let environment_id = uuid::Uuid::new_v4();
+        let mut key = api_key(ApiKeyAccess::Read, None);
+        let listed = secret(environment_id);
+        let mut in_folder = secret(environment_id);
+        in_folder.secret_name = "payments/prod/db/password".to_string();
+
+        key.allowed_paths = Some(vec!["payments/prod/*".to_string()]);
+
+        assert!(key.can_read(&in_folder, None));
+        assert!(!key.can_read(&listed, None));
+
+        key.allowed_secrets = Some(vec![listed.id]);
+
+        assert!(key.can_read(&in_folder, None));
+        assert!(key.can_read(&listed, None));
+        assert!(!key.can_read(&secret(environment_id), None));
+    }
+}
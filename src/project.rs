@@ -0,0 +1,409 @@
+use async_trait::async_trait;
+use sqlx::{Pool, Postgres};
+
+use crate::{error::HttpError, models::{Environment, Project}};
+
+pub const DEFAULT_NAME: &str = "default";
+
+// `chain` holds the environment bound to `$1` followed by the environments it
+// inherits from, nearest first. It follows at most 16 parents, so a loop in the
+// parents cannot make it run forever.
+pub const ENVIRONMENT_CHAIN: &str = r#"
+    WITH RECURSIVE chain AS (
+        SELECT id, parent_id, 0 AS depth FROM environments WHERE id = $1
+        UNION ALL
+        SELECT environments.id, environments.parent_id, chain.depth + 1
+        FROM environments
+        JOIN chain ON environments.id = chain.parent_id
+        WHERE chain.depth < 16
+    )
+"#;
+
+// Project names are unique per user and environment names per project. An
+// environment cannot be deleted while it still holds secrets, trashed ones
+// included.
+fn map_write_error(e: sqlx::Error, what: &str) -> HttpError {
+    match e {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            HttpError::unique_constraint_violation(format!("Another {} with this name already exists", what))
+        }
+        sqlx::Error::Database(db_err) if db_err.is_foreign_key_violation() => {
+            HttpError::unique_constraint_violation(format!("The {} still has secrets, purge them first", what))
+        }
+        e => HttpError::server_error(e.to_string()),
+    }
+}
+
+#[async_trait]
+pub trait ProjectRepository {
+    async fn get_projects(&self) -> Result<Vec<Project>, HttpError>;
+
+    async fn get_project(
+        &self,
+        project_id: uuid::Uuid
+    ) -> Result<Project, HttpError>;
+
+    async fn save_project(
+        &self,
+        name: &str
+    ) -> Result<Project, HttpError>;
+
+    async fn rename_project(
+        &self,
+        project_id: uuid::Uuid,
+        name: &str
+    ) -> Result<Project, HttpError>;
+
+    async fn delete_project(
+        &self,
+        project_id: uuid::Uuid
+    ) -> Result<(), HttpError>;
+
+    async fn get_environments(
+        &self,
+        project_id: uuid::Uuid
+    ) -> Result<Vec<Environment>, HttpError>;
+
+    async fn get_environment(
+        &self,
+        environment_id: uuid::Uuid
+    ) -> Result<Environment, HttpError>;
+
+    async fn get_environment_chain(
+        &self,
+        environment_id: uuid::Uuid
+    ) -> Result<Vec<uuid::Uuid>, HttpError>;
+
+    async fn save_environment(
+        &self,
+        project_id: uuid::Uuid,
+        name: &str,
+        parent_id: Option<uuid::Uuid>
+    ) -> Result<Environment, HttpError>;
+
+    async fn update_environment(
+        &self,
+        project_id: uuid::Uuid,
+        environment_id: uuid::Uuid,
+        name: &str,
+        parent_id: Option<uuid::Uuid>
+    ) -> Result<Environment, HttpError>;
+
+    async fn delete_environment(
+        &self,
+        project_id: uuid::Uuid,
+        environment_id: uuid::Uuid
+    ) -> Result<(), HttpError>;
+
+    async fn get_default_environment(&self) -> Result<Environment, HttpError>;
+
+    // The environment a request works in: the one it named, or the default
+    // environment when it named none.
+    async fn resolve_environment(
+        &self,
+        environment_id: Option<uuid::Uuid>
+    ) -> Result<Environment, HttpError> {
+        match environment_id {
+            Some(environment_id) => self.get_environment(environment_id).await,
+            None => self.get_default_environment().await,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PostgresProjectRepository<'a> {
+    pool: &'a Pool<Postgres>,
+}
+
+impl<'a> PostgresProjectRepository<'a> {
+    pub fn new(pool: &'a Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl<'a> ProjectRepository for PostgresProjectRepository<'a> {
+    async fn get_projects(&self) -> Result<Vec<Project>, HttpError> {
+        let projects = sqlx::query_as::<_, Project>(
+            r#"
+            SELECT id, name, created_at, updated_at
+            FROM projects
+            ORDER BY name
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok(projects)
+    }
+
+    async fn get_project(
+        &self,
+        project_id: uuid::Uuid,
+    ) -> Result<Project, HttpError> {
+        let project = sqlx::query_as::<_, Project>(
+            r#"
+            SELECT id, name, created_at, updated_at
+            FROM projects
+            WHERE id = $1
+            "#,
+        )
+        .bind(project_id)
+        .fetch_optional(self.pool)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::not_found("Project not found"))?;
+
+        Ok(project)
+    }
+
+    async fn save_project(
+        &self,
+        name: &str,
+    ) -> Result<Project, HttpError> {
+        let project = sqlx::query_as::<_, Project>(
+            r#"
+            INSERT INTO projects (name)
+            VALUES ($1)
+            RETURNING id, name, created_at, updated_at
+            "#,
+        )
+        .bind(name)
+        .fetch_one(self.pool)
+        .await
+        .map_err(|e| map_write_error(e, "project"))?;
+
+        Ok(project)
+    }
+
+    async fn rename_project(
+        &self,
+        project_id: uuid::Uuid,
+        name: &str,
+    ) -> Result<Project, HttpError> {
+        let project = sqlx::query_as::<_, Project>(
+            r#"
+            UPDATE projects
+            SET name = $2, updated_at = NOW()
+            WHERE id = $1
+            RETURNING id, name, created_at, updated_at
+            "#,
+        )
+        .bind(project_id)
+        .bind(name)
+        .fetch_optional(self.pool)
+        .await
+        .map_err(|e| map_write_error(e, "project"))?
+        .ok_or_else(|| HttpError::not_found("Project not found"))?;
+
+        Ok(project)
+    }
+
+    // Takes the project's environments with it, which fails while any of them
+    // still holds secrets.
+    async fn delete_project(
+        &self,
+        project_id: uuid::Uuid,
+    ) -> Result<(), HttpError> {
+        let result = sqlx::query("DELETE FROM projects WHERE id = $1")
+            .bind(project_id)
+            .execute(self.pool)
+            .await
+            .map_err(|e| map_write_error(e, "project"))?;
+
+        if result.rows_affected() == 0 {
+            return Err(HttpError::not_found("Project not found"));
+        }
+
+        Ok(())
+    }
+
+    async fn get_environments(
+        &self,
+        project_id: uuid::Uuid,
+    ) -> Result<Vec<Environment>, HttpError> {
+        let environments = sqlx::query_as::<_, Environment>(
+            r#"
+            SELECT id, project_id, parent_id, name, created_at, updated_at
+            FROM environments
+            WHERE project_id = $1
+            ORDER BY name
+            "#,
+        )
+        .bind(project_id)
+        .fetch_all(self.pool)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok(environments)
+    }
+
+    async fn get_environment(
+        &self,
+        environment_id: uuid::Uuid,
+    ) -> Result<Environment, HttpError> {
+        let environment = sqlx::query_as::<_, Environment>(
+            r#"
+            SELECT id, project_id, parent_id, name, created_at, updated_at
+            FROM environments
+            WHERE id = $1
+            "#,
+        )
+        .bind(environment_id)
+        .fetch_optional(self.pool)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::not_found("Environment not found"))?;
+
+        Ok(environment)
+    }
+
+    // The environment itself first, then its parent, grandparent and so on.
+    async fn get_environment_chain(
+        &self,
+        environment_id: uuid::Uuid,
+    ) -> Result<Vec<uuid::Uuid>, HttpError> {
+        let chain = sqlx::query_scalar::<_, uuid::Uuid>(
+            &format!("{} SELECT id FROM chain ORDER BY depth", ENVIRONMENT_CHAIN),
+        )
+        .bind(environment_id)
+        .fetch_all(self.pool)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        if chain.is_empty() {
+            return Err(HttpError::not_found("Environment not found"));
+        }
+
+        Ok(chain)
+    }
+
+    async fn save_environment(
+        &self,
+        project_id: uuid::Uuid,
+        name: &str,
+        parent_id: Option<uuid::Uuid>,
+    ) -> Result<Environment, HttpError> {
+        self.get_project(project_id).await?;
+
+        let environment = sqlx::query_as::<_, Environment>(
+            r#"
+            INSERT INTO environments (project_id, name, parent_id)
+            VALUES ($1, $2, $3)
+            RETURNING id, project_id, parent_id, name, created_at, updated_at
+            "#,
+        )
+        .bind(project_id)
+        .bind(name)
+        .bind(parent_id)
+        .fetch_one(self.pool)
+        .await
+        .map_err(|e| map_write_error(e, "environment"))?;
+
+        Ok(environment)
+    }
+
+    async fn update_environment(
+        &self,
+        project_id: uuid::Uuid,
+        environment_id: uuid::Uuid,
+        name: &str,
+        parent_id: Option<uuid::Uuid>,
+    ) -> Result<Environment, HttpError> {
+        let environment = sqlx::query_as::<_, Environment>(
+            r#"
+            UPDATE environments
+            SET name = $3, parent_id = $4, updated_at = NOW()
+            WHERE id = $1 AND project_id = $2
+            RETURNING id, project_id, parent_id, name, created_at, updated_at
+            "#,
+        )
+        .bind(environment_id)
+        .bind(project_id)
+        .bind(name)
+        .bind(parent_id)
+        .fetch_optional(self.pool)
+        .await
+        .map_err(|e| map_write_error(e, "environment"))?
+        .ok_or_else(|| HttpError::not_found("Environment not found"))?;
+
+        Ok(environment)
+    }
+
+    async fn delete_environment(
+        &self,
+        project_id: uuid::Uuid,
+        environment_id: uuid::Uuid,
+    ) -> Result<(), HttpError> {
+        let has_children: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM environments WHERE parent_id = $1)")
+            .bind(environment_id)
+            .fetch_one(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        if has_children {
+            return Err(HttpError::unique_constraint_violation("Other environments inherit from this environment"));
+        }
+
+        let result = sqlx::query("DELETE FROM environments WHERE id = $1 AND project_id = $2")
+            .bind(environment_id)
+            .bind(project_id)
+            .execute(self.pool)
+            .await
+            .map_err(|e| map_write_error(e, "environment"))?;
+
+        if result.rows_affected() == 0 {
+            return Err(HttpError::not_found("Environment not found"));
+        }
+
+        Ok(())
+    }
+
+    // Requests that name no environment use `default/default`, which is
+    // created the first time it is needed.
+    async fn get_default_environment(&self) -> Result<Environment, HttpError> {
+        let mut transaction = self.pool
+            .begin()
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        sqlx::query("INSERT INTO projects (name) VALUES ($1) ON CONFLICT (name) DO NOTHING")
+            .bind(DEFAULT_NAME)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO environments (project_id, name)
+            SELECT id, $1 FROM projects WHERE name = $1
+            ON CONFLICT (project_id, name) DO NOTHING
+            "#,
+        )
+        .bind(DEFAULT_NAME)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        let environment = sqlx::query_as::<_, Environment>(
+            r#"
+            SELECT environments.id, environments.project_id, environments.parent_id, environments.name, environments.created_at, environments.updated_at
+            FROM environments
+            JOIN projects ON projects.id = environments.project_id
+            WHERE projects.name = $1 AND environments.name = $1
+            "#,
+        )
+        .bind(DEFAULT_NAME)
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok(environment)
+    }
+}
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use axum::{middleware, Extension, Router};
 use tower_http::trace::TraceLayer;
 
-use crate::{handler::{auth::auth_handler, keys::get_secret_key, secrets::secrets_handler, secrets_version::secrets_version_handler, setting::setting_handler, user::users_handler}, middleware::auth, AppState};
+use crate::{handler::{auth::auth_handler, jwks::jwks_handler, keys::get_secret_key, machine::machine_handler, projects::projects_handler, secrets::secrets_handler, secrets_version::secrets_version_handler, setting::setting_handler, user::users_handler}, middleware::{api_key_auth, auth, require_full_access}, AppState};
 
 
 
@@ -13,11 +13,13 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
     .nest(
         "/users",
         users_handler()
+            .layer(middleware::from_fn(require_full_access))
             .layer(middleware::from_fn(auth))
     )
     .nest(
         "/setting", 
         setting_handler()
+            .layer(middleware::from_fn(require_full_access))
             .layer(middleware::from_fn(auth))
     )
     .nest(
@@ -25,14 +27,30 @@ pub fn create_router(app_state: Arc<AppState>) -> Router {
         secrets_handler()
             .layer(middleware::from_fn(auth))
     )
+    .nest(
+        "/projects",
+        projects_handler()
+            .layer(middleware::from_fn(auth))
+    )
     .nest(
         "/secrets_version", 
         secrets_version_handler()
             .layer(middleware::from_fn(auth))
     )
     .nest("/keys", get_secret_key())
+    .nest(
+        "/machine",
+        machine_handler()
+            .layer(middleware::from_fn(api_key_auth))
+    )
     .layer(TraceLayer::new_for_http())
-    .layer(Extension(app_state));
+    .layer(Extension(app_state.clone()));
 
-    Router::new().nest("/api", api_route)
+    Router::new()
+        .nest("/api", api_route)
+        .nest(
+            "/.well-known",
+            jwks_handler()
+                .layer(Extension(app_state))
+        )
 }
\ No newline at end of file
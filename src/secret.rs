@@ -1,7 +1,69 @@
+use std::collections::BTreeMap;
+
 use async_trait::async_trait;
-use sqlx::{Pool, Postgres};
+use sqlx::{types::Json, Pool, Postgres, Transaction};
+
+use crate::{error::HttpError, handler::secrets::SavedSecret, models::{InheritedSecret, Secret, SecretMetadata, SecretVersion}, project::ENVIRONMENT_CHAIN};
+
+// Narrows a listing by metadata: a secret must carry every tag and every label
+// given, and the owner when one is given. The default filter matches all.
+#[derive(Debug, Default, Clone)]
+pub struct SecretFilter {
+    pub tags: Vec<String>,
+    pub owner: Option<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+// Names are unique among the secrets of an environment that are not in the
+// trash.
+fn map_write_error(e: sqlx::Error) -> HttpError {
+    match e {
+        sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+            HttpError::unique_constraint_violation("A secret with this name already exists")
+        }
+        e => HttpError::server_error(e.to_string()),
+    }
+}
+
+// The secrets a caller limited to `ids` (`$ids`) and path patterns (`$paths`)
+// may see: those listed or matching a pattern as `secret_path::matches` does.
+// Without either limit every secret matches.
+fn scope_condition(ids: u8, paths: u8) -> String {
+    format!(
+        r#"((${ids}::uuid[] IS NULL AND ${paths}::text[] IS NULL)
+                OR id = ANY(${ids})
+                OR EXISTS (
+                    SELECT 1 FROM unnest(${paths}::text[]) AS pattern
+                    WHERE secret_name = pattern
+                        OR (right(pattern, 2) = '/*' AND starts_with(secret_name, left(pattern, -1)))
+                ))"#,
+        ids = ids,
+        paths = paths
+    )
+}
 
-use crate::{error::HttpError, handler::secrets::SavedSecret, models::{Secret, SecretVersion}};
+// Extends `ENVIRONMENT_CHAIN` with `visible`: every live secret the environment
+// sees through inheritance. Of the secrets sharing a name, the one defined
+// nearest to the environment has `position = 1`.
+fn visible_secrets() -> String {
+    format!(
+        r#"
+        {},
+        visible AS (
+            SELECT secrets.id, secrets.environment_id, secrets.secret_name, secrets.encrypted_secret_value,
+                secrets.version, secrets.created_at, secrets.updated_at, secrets.deleted_at,
+                secrets.description, secrets.owner, secrets.tags, secrets.labels,
+                chain.depth,
+                COUNT(*) OVER (PARTITION BY secrets.secret_name) AS definitions,
+                ROW_NUMBER() OVER (PARTITION BY secrets.secret_name ORDER BY chain.depth) AS position
+            FROM secrets
+            JOIN chain ON secrets.environment_id = chain.id
+            WHERE secrets.deleted_at IS NULL
+        )
+        "#,
+        ENVIRONMENT_CHAIN
+    )
+}
 
 #[async_trait]
 pub trait SecretRepository {
@@ -9,13 +71,49 @@ pub trait SecretRepository {
         &self,
         page: u32,
         limit: u32,
+        only_ids: Option<&[uuid::Uuid]>,
+        only_paths: Option<&[String]>,
+        filter: &SecretFilter,
     ) -> Result<(i64, Vec<Secret>), HttpError>;
 
+    async fn get_environment_secrets(
+        &self,
+        environment_id: uuid::Uuid,
+        page: u32,
+        limit: u32,
+        only_ids: Option<&[uuid::Uuid]>,
+        only_paths: Option<&[String]>,
+        filter: &SecretFilter,
+    ) -> Result<(i64, Vec<InheritedSecret>), HttpError>;
+
     async fn get_secrets_by_id(
         &self,
         secret_id: uuid::Uuid
     ) -> Result<Secret, HttpError>;
 
+    async fn get_secrets_by_name(
+        &self,
+        environment_id: uuid::Uuid,
+        secret_name: &str
+    ) -> Result<Secret, HttpError>;
+
+    async fn get_secrets_by_ids(
+        &self,
+        secret_ids: &[uuid::Uuid]
+    ) -> Result<Vec<Secret>, HttpError>;
+
+    async fn get_secrets_by_names(
+        &self,
+        environment_id: uuid::Uuid,
+        secret_names: &[String]
+    ) -> Result<Vec<Secret>, HttpError>;
+
+    async fn get_visible_secrets_by_ids(
+        &self,
+        environment_id: uuid::Uuid,
+        secret_ids: &[uuid::Uuid]
+    ) -> Result<Vec<Secret>, HttpError>;
+
     async fn get_secrets_version(
         &self,
         secret_id: uuid::Uuid,
@@ -34,6 +132,74 @@ pub trait SecretRepository {
         secret_name: String,
         encrypted_secret_value: Vec<u8>,
     ) -> Result<(), HttpError>;
+
+    // Writes that must succeed or fail together run in one transaction from
+    // `begin`, through the `_in` variants, and the caller commits it.
+    async fn begin(&self) -> Result<Transaction<'static, Postgres>, HttpError>;
+
+    async fn save_secrets_in(
+        &self,
+        transaction: &mut Transaction<'static, Postgres>,
+        saved_secrets: Vec<SavedSecret>
+    ) -> Result<(), HttpError>;
+
+    async fn edit_secrets_in(
+        &self,
+        transaction: &mut Transaction<'static, Postgres>,
+        secret_id: uuid::Uuid,
+        secret_name: String,
+        encrypted_secret_value: Vec<u8>,
+    ) -> Result<(), HttpError>;
+
+    async fn update_secret_metadata(
+        &self,
+        secret_id: uuid::Uuid,
+        metadata: &SecretMetadata,
+    ) -> Result<bool, HttpError>;
+
+    async fn get_folder(
+        &self,
+        environment_id: uuid::Uuid,
+        prefix: &str,
+        only_ids: Option<&[uuid::Uuid]>,
+        only_paths: Option<&[String]>,
+    ) -> Result<(Vec<String>, Vec<InheritedSecret>), HttpError>;
+
+    async fn get_secrets_under(
+        &self,
+        environment_id: uuid::Uuid,
+        prefix: &str,
+    ) -> Result<Vec<InheritedSecret>, HttpError>;
+
+    async fn move_folder(
+        &self,
+        environment_id: uuid::Uuid,
+        from_prefix: &str,
+        to_prefix: &str,
+    ) -> Result<u64, HttpError>;
+
+    async fn delete_secret(
+        &self,
+        secret_id: uuid::Uuid
+    ) -> Result<bool, HttpError>;
+
+    async fn get_deleted_secrets(
+        &self,
+        page: u32,
+        limit: u32,
+        only_ids: Option<&[uuid::Uuid]>,
+        environment_id: Option<uuid::Uuid>,
+    ) -> Result<(i64, Vec<Secret>), HttpError>;
+
+    async fn restore_secret(
+        &self,
+        secret_id: uuid::Uuid
+    ) -> Result<bool, HttpError>;
+
+    async fn purge_secret(
+        &self,
+        secret_id: uuid::Uuid
+    ) -> Result<bool, HttpError>;
 }
 
 #[derive(Debug)]
@@ -53,25 +219,116 @@ impl<'a> SecretRepository for PostgresSecretRespository<'a> {
         &self,
         page: u32,
         limit: u32,
+        only_ids: Option<&[uuid::Uuid]>,
+        only_paths: Option<&[String]>,
+        filter: &SecretFilter,
     ) -> Result<(i64, Vec<Secret>), HttpError> {
         let offset = (page - 1) * limit;
 
-        let query_count = "SELECT COUNT(*) as count FROM secrets";
-        let total_count = sqlx::query_scalar(query_count)
+        let query_count = format!(
+            r#"
+            SELECT COUNT(*) as count FROM secrets
+            WHERE deleted_at IS NULL AND {}
+                AND tags @> $2 AND ($3::text IS NULL OR owner = $3) AND labels @> $4
+            "#,
+            scope_condition(1, 5)
+        );
+        let total_count = sqlx::query_scalar(&query_count)
+            .bind(only_ids)
+            .bind(&filter.tags)
+            .bind(filter.owner.as_deref())
+            .bind(Json(&filter.labels))
+            .bind(only_paths)
             .fetch_one(self.pool) // Ensure self.pool is of type Pool<Postgres>
             .await
             .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-        let query_secrets = r#"
-            SELECT id, secret_name, encrypted_secret_value, version, created_at, updated_at
+        let query_secrets = format!(
+            r#"
+            SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels
             FROM secrets
+            WHERE deleted_at IS NULL AND {}
+                AND tags @> $4 AND ($5::text IS NULL OR owner = $5) AND labels @> $6
             ORDER BY created_at DESC
             LIMIT $1 OFFSET $2
-        "#;
+            "#,
+            scope_condition(3, 7)
+        );
 
-        let secrets = sqlx::query_as::<_, Secret>(query_secrets)
+        let secrets = sqlx::query_as::<_, Secret>(&query_secrets)
+            .bind(limit as i32)
+            .bind(offset as i32)
+            .bind(only_ids)
+            .bind(&filter.tags)
+            .bind(filter.owner.as_deref())
+            .bind(Json(&filter.labels))
+            .bind(only_paths)
+            .fetch_all(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok((total_count, secrets))
+    }
+
+    // The secrets an environment sees: its own, plus those of the environments
+    // it inherits from that it does not override. The filter applies to the
+    // definition the environment sees, not to the ones it overrides.
+    async fn get_environment_secrets(
+        &self,
+        environment_id: uuid::Uuid,
+        page: u32,
+        limit: u32,
+        only_ids: Option<&[uuid::Uuid]>,
+        only_paths: Option<&[String]>,
+        filter: &SecretFilter,
+    ) -> Result<(i64, Vec<InheritedSecret>), HttpError> {
+        let offset = (page - 1) * limit;
+
+        let query_count = format!(
+            r#"
+            {}
+            SELECT COUNT(*) as count FROM visible
+            WHERE position = 1 AND {}
+                AND tags @> $3 AND ($4::text IS NULL OR owner = $4) AND labels @> $5
+            "#,
+            visible_secrets(),
+            scope_condition(2, 6)
+        );
+
+        let total_count = sqlx::query_scalar(&query_count)
+            .bind(environment_id)
+            .bind(only_ids)
+            .bind(&filter.tags)
+            .bind(filter.owner.as_deref())
+            .bind(Json(&filter.labels))
+            .bind(only_paths)
+            .fetch_one(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        let query_secrets = format!(
+            r#"
+            {}
+            SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels, depth, definitions
+            FROM visible
+            WHERE position = 1 AND {}
+                AND tags @> $3 AND ($4::text IS NULL OR owner = $4) AND labels @> $5
+            ORDER BY created_at DESC
+            LIMIT $6 OFFSET $7
+            "#,
+            visible_secrets(),
+            scope_condition(2, 8)
+        );
+
+        let secrets = sqlx::query_as::<_, InheritedSecret>(&query_secrets)
+            .bind(environment_id)
+            .bind(only_ids)
+            .bind(&filter.tags)
+            .bind(filter.owner.as_deref())
+            .bind(Json(&filter.labels))
             .bind(limit as i32)
             .bind(offset as i32)
+            .bind(only_paths)
             .fetch_all(self.pool)
             .await
             .map_err(|e| HttpError::server_error(e.to_string()))?;
@@ -84,21 +341,121 @@ impl<'a> SecretRepository for PostgresSecretRespository<'a> {
         secret_id: uuid::Uuid,
     ) -> Result<Secret, HttpError> {
         let query_secret = r#"
-            SELECT id, secret_name, encrypted_secret_value, version, created_at, updated_at
+            SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels
             FROM secrets
-            WHERE id = $1
+            WHERE id = $1 AND deleted_at IS NULL
         "#;
 
         let secret_versions = sqlx::query_as::<_, Secret>(query_secret)
         .bind(secret_id)
-        .fetch_one(self.pool)
+        .fetch_optional(self.pool)
         .await
-        .map_err(|e| HttpError::server_error(e.to_string()))?;
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::not_found("Secret not found"))?;
 
         Ok(secret_versions)
 
     }
 
+    async fn get_secrets_by_name(
+        &self,
+        environment_id: uuid::Uuid,
+        secret_name: &str,
+    ) -> Result<Secret, HttpError> {
+        let query_secret = format!(
+            r#"
+            {}
+            SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels
+            FROM visible
+            WHERE position = 1 AND secret_name = $2
+            "#,
+            visible_secrets()
+        );
+
+        let secret = sqlx::query_as::<_, Secret>(&query_secret)
+            .bind(environment_id)
+            .bind(secret_name)
+            .fetch_optional(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?
+            .ok_or_else(|| HttpError::not_found("Secret not found"))?;
+
+        Ok(secret)
+    }
+
+    async fn get_secrets_by_ids(
+        &self,
+        secret_ids: &[uuid::Uuid],
+    ) -> Result<Vec<Secret>, HttpError> {
+        let query_secrets = r#"
+            SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels
+            FROM secrets
+            WHERE id = ANY($1) AND deleted_at IS NULL
+        "#;
+
+        let secrets = sqlx::query_as::<_, Secret>(query_secrets)
+            .bind(secret_ids)
+            .fetch_all(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok(secrets)
+    }
+
+    async fn get_secrets_by_names(
+        &self,
+        environment_id: uuid::Uuid,
+        secret_names: &[String],
+    ) -> Result<Vec<Secret>, HttpError> {
+        let query_secrets = format!(
+            r#"
+            {}
+            SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels
+            FROM visible
+            WHERE position = 1 AND secret_name = ANY($2)
+            ORDER BY created_at DESC
+            "#,
+            visible_secrets()
+        );
+
+        let secrets = sqlx::query_as::<_, Secret>(&query_secrets)
+            .bind(environment_id)
+            .bind(secret_names)
+            .fetch_all(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok(secrets)
+    }
+
+    // Only the definitions the environment sees: the id of a parent's secret
+    // that the environment overrides, or of one outside its chain, finds
+    // nothing.
+    async fn get_visible_secrets_by_ids(
+        &self,
+        environment_id: uuid::Uuid,
+        secret_ids: &[uuid::Uuid],
+    ) -> Result<Vec<Secret>, HttpError> {
+        let query_secrets = format!(
+            r#"
+            {}
+            SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels
+            FROM visible
+            WHERE position = 1 AND id = ANY($2)
+            "#,
+            visible_secrets()
+        );
+
+        let secrets = sqlx::query_as::<_, Secret>(&query_secrets)
+            .bind(environment_id)
+            .bind(secret_ids)
+            .fetch_all(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok(secrets)
+    }
+
     async fn get_secrets_version(
         &self,
         secret_id: uuid::Uuid,
@@ -111,6 +468,7 @@ impl<'a> SecretRepository for PostgresSecretRespository<'a> {
             SELECT COUNT(*) as count
             FROM secret_versions
             WHERE secret_id = $1
+                AND secret_id IN (SELECT id FROM secrets WHERE deleted_at IS NULL)
         "#;
 
         let total_count = sqlx::query_scalar(query_count)
@@ -123,6 +481,7 @@ impl<'a> SecretRepository for PostgresSecretRespository<'a> {
             SELECT id, secret_id, secret_name, encrypted_secret_value, version, created_at, updated_at
             FROM secret_versions
             WHERE secret_id = $1
+                AND secret_id IN (SELECT id FROM secrets WHERE deleted_at IS NULL)
             ORDER BY created_at DESC
             LIMIT $2 OFFSET $3
         "#;
@@ -143,13 +502,57 @@ impl<'a> SecretRepository for PostgresSecretRespository<'a> {
         &self,
         saved_secrets: Vec<SavedSecret>
     ) -> Result<(), HttpError> {
-        let mut query = String::from("INSERT INTO secrets (secret_name, encrypted_secret_value, version) VALUES ");
+        let mut transaction = self.begin().await?;
+
+        self.save_secrets_in(&mut transaction, saved_secrets).await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))
+    }
+
+    // A rename that clashes with another secret must not leave a stray
+    // version behind.
+    async fn edit_secrets(
+        &self,
+        secret_id: uuid::Uuid,
+        secret_name: String,
+        encrypted_secret_value: Vec<u8>,
+    ) -> Result<(), HttpError> {
+        let mut transaction = self.begin().await?;
+
+        self.edit_secrets_in(&mut transaction, secret_id, secret_name, encrypted_secret_value).await?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))
+    }
+
+    async fn begin(&self) -> Result<Transaction<'static, Postgres>, HttpError> {
+        self.pool
+            .begin()
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))
+    }
+
+    async fn save_secrets_in(
+        &self,
+        transaction: &mut Transaction<'static, Postgres>,
+        saved_secrets: Vec<SavedSecret>
+    ) -> Result<(), HttpError> {
+        let mut query = String::from("INSERT INTO secrets (environment_id, secret_name, encrypted_secret_value, version, description, owner, tags, labels) VALUES ");
         
         for (i, _secret) in saved_secrets.iter().enumerate() {
             if i > 0 {
                 query.push_str(", ");
             }
-            query.push_str(&format!("(${}, ${}, ${})", (i * 3 + 1), (i * 3 + 2), (i * 3 + 3)));
+            let first = i * 8;
+            query.push_str(&format!(
+                "(${}, ${}, ${}, ${}, ${}, ${}, ${}, ${})",
+                first + 1, first + 2, first + 3, first + 4, first + 5, first + 6, first + 7, first + 8
+            ));
         }
     
         // Prepare the query using a query builder
@@ -158,38 +561,46 @@ impl<'a> SecretRepository for PostgresSecretRespository<'a> {
         // Bind each parameter directly in the loop
         for secret in saved_secrets.iter() {
             query_builder = query_builder
+                .bind(secret.environment_id) // Bind environment_id
                 .bind(secret.secret_name.clone()) // Bind secret_name
                 .bind(secret.encrypted_secret_value.clone()) // Bind encrypted_secret_value
-                .bind(secret.version.clone()); // Bind version
+                .bind(secret.version) // Bind version
+                .bind(secret.metadata.description.clone())
+                .bind(secret.metadata.owner.clone())
+                .bind(secret.metadata.tags.clone())
+                .bind(secret.metadata.labels.clone());
         }
     
         // Execute the query
         let _result = query_builder
-            .execute(self.pool)
+            .execute(&mut **transaction)
             .await
-            .map_err(|e| HttpError::server_error(e.to_string()))?;
+            .map_err(map_write_error)?;
     
         Ok(())
     }
 
-    async fn edit_secrets(
+    async fn edit_secrets_in(
         &self,
+        transaction: &mut Transaction<'static, Postgres>,
         secret_id: uuid::Uuid,
         secret_name: String,
         encrypted_secret_value: Vec<u8>,
     ) -> Result<(), HttpError> {
 
         let query = r#"
-        SELECT id, secret_name, encrypted_secret_value, version, created_at, updated_at 
+        SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels
         FROM secrets 
-        WHERE id = $1
+        WHERE id = $1 AND deleted_at IS NULL
+        FOR UPDATE
         "#;
 
         let current_secret = sqlx::query_as::<_, Secret>(query)
-        .bind(&secret_id)
-        .fetch_one(self.pool)
+        .bind(secret_id)
+        .fetch_optional(&mut **transaction)
         .await
-        .map_err(|e| HttpError::server_error(e.to_string()))?;
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(|| HttpError::not_found("Secret not found"))?;
 
         sqlx::query(
             r"
@@ -201,7 +612,7 @@ impl<'a> SecretRepository for PostgresSecretRespository<'a> {
         .bind(current_secret.secret_name)
         .bind(current_secret.encrypted_secret_value)
         .bind(current_secret.version)
-        .execute(self.pool)
+        .execute(&mut **transaction)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -215,11 +626,279 @@ impl<'a> SecretRepository for PostgresSecretRespository<'a> {
         .bind(secret_name)
         .bind(encrypted_secret_value)
         .bind(secret_id)
+        .execute(&mut **transaction)
+        .await
+        .map_err(map_write_error)?;
+
+        Ok(())
+    }
+
+    // Replaces the metadata of a live secret. The value and version stay as
+    // they are, and no version is recorded.
+    async fn update_secret_metadata(
+        &self,
+        secret_id: uuid::Uuid,
+        metadata: &SecretMetadata,
+    ) -> Result<bool, HttpError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE secrets
+            SET description = $2, owner = $3, tags = $4, labels = $5, updated_at = NOW()
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(secret_id)
+        .bind(metadata.description.as_deref())
+        .bind(metadata.owner.as_deref())
+        .bind(&metadata.tags)
+        .bind(&metadata.labels)
         .execute(self.pool)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+        Ok(result.rows_affected() > 0)
+    }
 
-        Ok(())
+    // Lists the direct children of the folder whose paths start with `prefix`,
+    // as the environment sees them through inheritance: the names of
+    // sub-folders, and the secrets stored directly inside it.
+    async fn get_folder(
+        &self,
+        environment_id: uuid::Uuid,
+        prefix: &str,
+        only_ids: Option<&[uuid::Uuid]>,
+        only_paths: Option<&[String]>,
+    ) -> Result<(Vec<String>, Vec<InheritedSecret>), HttpError> {
+        let rest_start = prefix.chars().count() as i32 + 1;
+
+        let query_folders = format!(
+            r#"
+            {}
+            SELECT DISTINCT split_part(substr(secret_name, $3), '/', 1) AS folder
+            FROM visible
+            WHERE position = 1
+                AND starts_with(secret_name, $2)
+                AND strpos(substr(secret_name, $3), '/') > 0
+                AND {}
+            ORDER BY folder
+            "#,
+            visible_secrets(),
+            scope_condition(4, 5)
+        );
+
+        let folders = sqlx::query_scalar::<_, String>(&query_folders)
+            .bind(environment_id)
+            .bind(prefix)
+            .bind(rest_start)
+            .bind(only_ids)
+            .bind(only_paths)
+            .fetch_all(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        let query_secrets = format!(
+            r#"
+            {}
+            SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels, depth, definitions
+            FROM visible
+            WHERE position = 1
+                AND starts_with(secret_name, $2)
+                AND strpos(substr(secret_name, $3), '/') = 0
+                AND {}
+            ORDER BY secret_name
+            "#,
+            visible_secrets(),
+            scope_condition(4, 5)
+        );
+
+        let secrets = sqlx::query_as::<_, InheritedSecret>(&query_secrets)
+            .bind(environment_id)
+            .bind(prefix)
+            .bind(rest_start)
+            .bind(only_ids)
+            .bind(only_paths)
+            .fetch_all(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok((folders, secrets))
     }
-}
\ No newline at end of file
+
+    // Every secret the environment sees whose path starts with `prefix`, at any
+    // depth below it.
+    async fn get_secrets_under(
+        &self,
+        environment_id: uuid::Uuid,
+        prefix: &str,
+    ) -> Result<Vec<InheritedSecret>, HttpError> {
+        let query_secrets = format!(
+            r#"
+            {}
+            SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels, depth, definitions
+            FROM visible
+            WHERE position = 1 AND starts_with(secret_name, $2)
+            ORDER BY secret_name
+            "#,
+            visible_secrets()
+        );
+
+        let secrets = sqlx::query_as::<_, InheritedSecret>(&query_secrets)
+            .bind(environment_id)
+            .bind(prefix)
+            .fetch_all(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok(secrets)
+    }
+
+    // Moves every live secret under `from_prefix` to `to_prefix`, keeping the
+    // rest of each path. Only the environment's own secrets move, never
+    // inherited ones. Names that would clash roll the whole move back.
+    async fn move_folder(
+        &self,
+        environment_id: uuid::Uuid,
+        from_prefix: &str,
+        to_prefix: &str,
+    ) -> Result<u64, HttpError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE secrets
+            SET secret_name = $2 || substr(secret_name, $3), updated_at = NOW()
+            WHERE deleted_at IS NULL AND starts_with(secret_name, $1) AND environment_id = $4
+            "#,
+        )
+        .bind(from_prefix)
+        .bind(to_prefix)
+        .bind(from_prefix.chars().count() as i32 + 1)
+        .bind(environment_id)
+        .execute(self.pool)
+        .await
+        .map_err(map_write_error)?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_secret(
+        &self,
+        secret_id: uuid::Uuid,
+    ) -> Result<bool, HttpError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE secrets
+            SET deleted_at = NOW()
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(secret_id)
+        .execute(self.pool)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_deleted_secrets(
+        &self,
+        page: u32,
+        limit: u32,
+        only_ids: Option<&[uuid::Uuid]>,
+        environment_id: Option<uuid::Uuid>,
+    ) -> Result<(i64, Vec<Secret>), HttpError> {
+        let offset = (page - 1) * limit;
+
+        let query_count = r#"
+            SELECT COUNT(*) as count FROM secrets
+            WHERE deleted_at IS NOT NULL
+                AND ($1::uuid[] IS NULL OR id = ANY($1))
+                AND ($2::uuid IS NULL OR environment_id = $2)
+        "#;
+        let total_count = sqlx::query_scalar(query_count)
+            .bind(only_ids)
+            .bind(environment_id)
+            .fetch_one(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        let query_secrets = r#"
+            SELECT id, environment_id, secret_name, encrypted_secret_value, version, created_at, updated_at, deleted_at, description, owner, tags, labels
+            FROM secrets
+            WHERE deleted_at IS NOT NULL
+                AND ($3::uuid[] IS NULL OR id = ANY($3))
+                AND ($4::uuid IS NULL OR environment_id = $4)
+            ORDER BY deleted_at DESC
+            LIMIT $1 OFFSET $2
+        "#;
+
+        let secrets = sqlx::query_as::<_, Secret>(query_secrets)
+            .bind(limit as i32)
+            .bind(offset as i32)
+            .bind(only_ids)
+            .bind(environment_id)
+            .fetch_all(self.pool)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok((total_count, secrets))
+    }
+
+    async fn restore_secret(
+        &self,
+        secret_id: uuid::Uuid,
+    ) -> Result<bool, HttpError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE secrets
+            SET deleted_at = NULL
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(secret_id)
+        .execute(self.pool)
+        .await
+        .map_err(map_write_error)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Only secrets already in the trash can be purged. Versions are removed
+    // first, so this works whether or not the table cascades on delete.
+    async fn purge_secret(
+        &self,
+        secret_id: uuid::Uuid,
+    ) -> Result<bool, HttpError> {
+        let mut transaction = self.pool
+            .begin()
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM secret_versions
+            WHERE secret_id IN (SELECT id FROM secrets WHERE id = $1 AND deleted_at IS NOT NULL)
+            "#,
+        )
+        .bind(secret_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        let result = sqlx::query(
+            r#"
+            DELETE FROM secrets
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(secret_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,94 @@
+use std::{fs::File, io::BufReader, sync::Arc};
+
+use axum::{extract::{ConnectInfo, Request}, Router};
+use hyper::body::Incoming;
+use hyper_util::{rt::{TokioExecutor, TokioIo}, server::conn::auto::Builder, service::TowerToHyperService};
+use tokio::net::TcpListener;
+use tokio_rustls::{rustls::{crypto::ring::default_provider, server::WebPkiClientVerifier, RootCertStore, ServerConfig}, TlsAcceptor};
+use tower::ServiceExt;
+
+use crate::{config::TlsConfig, utils::client_cert::PeerCertificate};
+
+pub fn server_config(tls: &TlsConfig) -> Arc<ServerConfig> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&tls.cert_path).expect("Failed to open TLS_CERT_PATH")))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Invalid TLS_CERT_PATH");
+
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(&tls.key_path).expect("Failed to open TLS_KEY_PATH")))
+        .expect("Invalid TLS_KEY_PATH")
+        .expect("TLS_KEY_PATH does not contain a private key");
+
+    let provider = Arc::new(default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .expect("Unsupported TLS protocol versions");
+
+    // Client certificates are optional at the TLS layer: browsers and token
+    // users connect without one, and the auth middleware decides per request.
+    let builder = match &tls.client_ca_path {
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in rustls_pemfile::certs(&mut BufReader::new(File::open(client_ca_path).expect("Failed to open TLS_CLIENT_CA_PATH"))) {
+                roots.add(cert.expect("Invalid TLS_CLIENT_CA_PATH")).expect("Invalid TLS_CLIENT_CA_PATH");
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .allow_unauthenticated()
+                .build()
+                .expect("Failed to build client certificate verifier");
+
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server_config = builder
+        .with_single_cert(certs, key)
+        .expect("Invalid TLS certificate or key");
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Arc::new(server_config)
+}
+
+pub async fn serve(listener: TcpListener, app: Router, server_config: Arc<ServerConfig>) {
+    let acceptor = TlsAcceptor::from(server_config);
+
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                println!("🔥 Failed to accept connection: {:?}", err);
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+
+            let peer_certificate = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .map(|cert| PeerCertificate::from_der(cert));
+
+            let service = app.map_request(move |mut req: Request<Incoming>| {
+                req.extensions_mut().insert(ConnectInfo(remote_addr));
+                if let Some(peer_certificate) = &peer_certificate {
+                    req.extensions_mut().insert(peer_certificate.clone());
+                }
+                req
+            });
+
+            let _ = Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service))
+                .await;
+        });
+    }
+}
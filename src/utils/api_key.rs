@@ -0,0 +1,251 @@
+use std::net::IpAddr;
+
+use axum::http::{header, HeaderMap};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::{db::{ApiKeyExt, DBClient}, error::{ErrorMessage, HttpError}, models::{ApiKey, Secret}, project::ProjectRepository, secret::SecretRepository, utils::api_key_usage::UsageTracker};
+
+// Keys look like `sbk_<environment>_<random>_<checksum>`, matching
+// `sbk_[a-z0-9]{1,16}_[A-Za-z0-9]{43}_[A-Za-z0-9]{6}`. The random part is 43
+// base62 characters (just over 256 bits) and the checksum is the CRC32 of
+// everything before it, also in base62.
+pub const KEY_PREFIX: &str = "sbk";
+pub const RANDOM_LENGTH: usize = 43;
+const CHECKSUM_LENGTH: usize = 6;
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+// Keys issued before the current format: a millisecond timestamp followed by
+// 10 alphanumeric characters.
+const LEGACY_PREFIX_LENGTH: usize = 12;
+const LEGACY_TIMESTAMP_LENGTH: usize = 13;
+const LEGACY_RANDOM_LENGTH: usize = 10;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+// Stored prefix keeps the environment plus the first 8 random characters, so
+// keys stay recognisable in listings without revealing enough to matter.
+const PREFIX_RANDOM_LENGTH: usize = 8;
+
+// API keys are long random strings, so a keyed hash is enough to keep a
+// database leak from exposing them; a slow password hash would only add
+// latency to every request.
+pub fn hash(secret: &str, key: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(key.as_bytes());
+
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+pub fn prefix(key: &str) -> String {
+    match parse(key) {
+        Some((environment, random, _)) => format!("{}_{}_{}", KEY_PREFIX, environment, &random[..PREFIX_RANDOM_LENGTH]),
+        None => key.chars().take(LEGACY_PREFIX_LENGTH).collect(),
+    }
+}
+
+// Reads the key from `Authorization: ApiKey <key>` or `X-API-Key: <key>`.
+pub fn from_headers(headers: &HeaderMap) -> Option<String> {
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("ApiKey "));
+
+    let api_key_header = headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    authorization
+        .or(api_key_header)
+        .map(|key| key.trim().to_string())
+}
+
+pub fn checksum(body: &str) -> String {
+    let mut value = crc32fast::hash(body.as_bytes());
+    let mut encoded = [b'0'; CHECKSUM_LENGTH];
+
+    for position in encoded.iter_mut().rev() {
+        *position = BASE62[(value % 62) as usize];
+        value /= 62;
+    }
+
+    String::from_utf8(encoded.to_vec()).unwrap()
+}
+
+pub fn is_valid_environment(environment: &str) -> bool {
+    (1..=16).contains(&environment.len())
+        && environment.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+}
+
+// Splits a key into environment, random part and checksum, if it has the
+// current shape. The checksum is not verified here.
+fn parse(key: &str) -> Option<(&str, &str, &str)> {
+    let rest = key.strip_prefix(KEY_PREFIX)?.strip_prefix('_')?;
+    let (environment, rest) = rest.split_once('_')?;
+    let (random, key_checksum) = rest.split_once('_')?;
+
+    let well_formed = is_valid_environment(environment)
+        && random.len() == RANDOM_LENGTH
+        && random.bytes().all(|b| b.is_ascii_alphanumeric())
+        && key_checksum.len() == CHECKSUM_LENGTH;
+
+    well_formed.then_some((environment, random, key_checksum))
+}
+
+// The key comes straight from the request, so it is only sliced once it is
+// known to be ASCII and every index is a character boundary.
+fn is_legacy(key: &str) -> bool {
+    key.is_ascii()
+        && key.len() == LEGACY_TIMESTAMP_LENGTH + LEGACY_RANDOM_LENGTH
+        && key[..LEGACY_TIMESTAMP_LENGTH].bytes().all(|b| b.is_ascii_digit())
+        && key[LEGACY_TIMESTAMP_LENGTH..].bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+// Rejects mistyped, truncated or made-up keys without touching the database.
+pub fn is_well_formed(key: &str) -> bool {
+    match parse(key) {
+        Some((_, _, key_checksum)) => {
+            let body = &key[..key.len() - CHECKSUM_LENGTH - 1];
+            checksum(body) == key_checksum
+        }
+        None => is_legacy(key),
+    }
+}
+
+pub fn mask(prefix: &str) -> String {
+    format!("{}****", prefix)
+}
+
+// Keys created before hashing was introduced are hashed on startup and their
+// plaintext removed.
+pub async fn hash_plaintext_keys(db_client: &DBClient, secret: &str) -> Result<usize, sqlx::Error> {
+    let plaintext_keys = db_client.get_plaintext_api_keys().await?;
+
+    for (api_key_id, key) in &plaintext_keys {
+        db_client.set_api_key_hash(*api_key_id, hash(secret, key)).await?;
+    }
+
+    Ok(plaintext_keys.len())
+}
+
+// How many rejected attempts are kept, and listed, for each key.
+pub const REJECTIONS_KEPT: i64 = 100;
+
+// Enforces the key's address allowlist and records rejected attempts, so the
+// owner can see where a leaked key is being tried from. Rejections go through
+// the usage tracker, which batches them and merges repeats from one address.
+pub fn check_client_address(usage: &UsageTracker, api_key: &ApiKey, client_ip: IpAddr) -> Result<(), HttpError> {
+    if api_key.allows_address(client_ip) {
+        return Ok(());
+    }
+
+    usage.reject(api_key.id, client_ip);
+
+    Err(HttpError::forbidden(ErrorMessage::AddressNotAllowed.to_string()))
+}
+
+// The secret environment a request made with this key works in. Not to be
+// confused with the deployment environment in the key itself: a key scoped to
+// a project environment always reads from it, and naming another one is
+// refused.
+pub fn scope_environment_id(api_key: &ApiKey, requested: Option<uuid::Uuid>) -> Result<Option<uuid::Uuid>, HttpError> {
+    match (api_key.environment_id, requested) {
+        (Some(scoped), Some(requested)) if scoped != requested => {
+            Err(HttpError::forbidden(ErrorMessage::InsufficientScope.to_string()))
+        }
+        (Some(scoped), _) => Ok(Some(scoped)),
+        (None, requested) => Ok(requested),
+    }
+}
+
+// The environments a scoped key reads from: its own and the ones it inherits
+// from. `None` for keys that are not scoped to an environment.
+pub async fn readable_environment_ids(
+    api_key: &ApiKey,
+    project_repo: &(impl ProjectRepository + Sync),
+) -> Result<Option<Vec<uuid::Uuid>>, HttpError> {
+    match api_key.environment_id {
+        Some(environment_id) => Ok(Some(project_repo.get_environment_chain(environment_id).await?)),
+        None => Ok(None),
+    }
+}
+
+// Looks secrets up by id for this key. A scoped key only resolves an id to the
+// definition its environment sees, never to a parent's secret it overrides.
+pub async fn secrets_by_ids(
+    api_key: &ApiKey,
+    repo: &(impl SecretRepository + Sync),
+    secret_ids: &[uuid::Uuid],
+) -> Result<Vec<Secret>, HttpError> {
+    match api_key.environment_id {
+        Some(environment_id) => repo.get_visible_secrets_by_ids(environment_id, secret_ids).await,
+        None => repo.get_secrets_by_ids(secret_ids).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::generate_key::generate_api_key;
+
+    use super::*;
+
+    const KEY: &str = "sbk_live_AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA_23ayzq";
+
+    #[test]
+    fn checksum_is_crc32_in_base62() {
+        assert_eq!(checksum("123456789"), "3jZRME");
+        assert_eq!(checksum(""), "000000");
+    }
+
+    #[test]
+    fn parses_current_keys() {
+        assert_eq!(parse(KEY), Some(("live", "A".repeat(RANDOM_LENGTH).as_str(), "23ayzq")));
+        assert_eq!(prefix(KEY), "sbk_live_AAAAAAAA");
+    }
+
+    #[test]
+    fn rejects_malformed_shapes() {
+        assert_eq!(parse("sbk_live_AAAA_23ayzq"), None);
+        assert_eq!(parse(&KEY.replace("sbk_", "xyz_")), None);
+        assert_eq!(parse(&KEY.replace("_live_", "_LIVE_")), None);
+        assert_eq!(parse(&KEY.replace("_live_", "__")), None);
+        assert_eq!(parse(&KEY.replace("_23ayzq", "_23ayz")), None);
+        assert_eq!(parse(&KEY.replacen('A', "-", 1)), None);
+    }
+
+    #[test]
+    fn accepts_generated_keys() {
+        let key = generate_api_key("test");
+
+        assert!(key.starts_with("sbk_test_"));
+        assert!(is_well_formed(&key));
+    }
+
+    #[test]
+    fn rejects_wrong_checksums() {
+        assert!(is_well_formed(KEY));
+        assert!(!is_well_formed(&KEY.replace("_23ayzq", "_23ayzr")));
+        assert!(!is_well_formed(&KEY.replacen('A', "B", 1)));
+    }
+
+    #[test]
+    fn accepts_legacy_keys() {
+        assert!(is_well_formed("1729262063123Ab3dE5gH7j"));
+        assert_eq!(prefix("1729262063123Ab3dE5gH7j"), "1729262063123Ab3dE5gH7j"[..LEGACY_PREFIX_LENGTH]);
+        assert!(!is_well_formed("1729262063123Ab3dE5gH7"));
+        assert!(!is_well_formed("172926206312xAb3dE5gH7j"));
+    }
+
+    #[test]
+    fn rejects_multibyte_input_without_panicking() {
+        // 23 bytes, like a legacy key, with a character straddling the
+        // boundary between timestamp and random part.
+        let key = "172926206312é3dE5gH7jK";
+        assert_eq!(key.len(), LEGACY_TIMESTAMP_LENGTH + LEGACY_RANDOM_LENGTH);
+
+        assert!(!is_well_formed(key));
+        assert!(!is_well_formed("sbk_live_ééééééééééééééééééééé_23ayzq"));
+        assert!(!is_well_formed("😀"));
+    }
+}
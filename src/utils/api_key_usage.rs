@@ -0,0 +1,141 @@
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::mpsc;
+
+use crate::{db::{ApiKeyExt, ApiKeyRejectedAddress, ApiKeyUsage, DBClient}, utils::api_key::REJECTIONS_KEPT};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+const CHANNEL_CAPACITY: usize = 10_000;
+const MAX_PENDING_KEYS: usize = 500;
+
+#[derive(Debug)]
+struct UsageEvent {
+    api_key_id: uuid::Uuid,
+    ip: IpAddr,
+    used_at: DateTime<Utc>,
+    rejected: bool,
+}
+
+#[derive(Debug, Default)]
+struct Pending {
+    usages: HashMap<uuid::Uuid, ApiKeyUsage>,
+    rejections: HashMap<(uuid::Uuid, IpAddr), DateTime<Utc>>,
+}
+
+// Collects API key usage and rejected addresses off the request path. Requests
+// only push an event onto a channel; a background task merges events per key,
+// and rejections per key and address, and writes them every few seconds, so
+// both can lag by up to `FLUSH_INTERVAL`.
+#[derive(Debug, Clone)]
+pub struct UsageTracker {
+    sender: mpsc::Sender<UsageEvent>,
+}
+
+impl UsageTracker {
+    pub fn start(db_client: DBClient) -> Self {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+
+        tokio::spawn(run(db_client, receiver));
+
+        UsageTracker { sender }
+    }
+
+    // Usage is best effort: when the writer falls behind, events are dropped
+    // rather than slowing requests down.
+    pub fn record(&self, api_key_id: uuid::Uuid, ip: IpAddr) {
+        let _ = self.sender.try_send(UsageEvent {
+            api_key_id,
+            ip,
+            used_at: Utc::now(),
+            rejected: false,
+        });
+    }
+
+    pub fn reject(&self, api_key_id: uuid::Uuid, ip: IpAddr) {
+        let _ = self.sender.try_send(UsageEvent {
+            api_key_id,
+            ip,
+            used_at: Utc::now(),
+            rejected: true,
+        });
+    }
+}
+
+impl Pending {
+    fn add(&mut self, event: UsageEvent) {
+        if event.rejected {
+            let rejected_at = self.rejections.entry((event.api_key_id, event.ip)).or_insert(event.used_at);
+            *rejected_at = (*rejected_at).max(event.used_at);
+
+            return;
+        }
+
+        let usage = self.usages.entry(event.api_key_id).or_insert_with(|| ApiKeyUsage {
+            api_key_id: event.api_key_id,
+            request_count: 0,
+            last_used_at: event.used_at,
+            last_used_ip: event.ip.to_string(),
+        });
+
+        usage.request_count += 1;
+
+        if event.used_at >= usage.last_used_at {
+            usage.last_used_at = event.used_at;
+            usage.last_used_ip = event.ip.to_string();
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.usages.len() + self.rejections.len()
+    }
+}
+
+async fn run(db_client: DBClient, mut receiver: mpsc::Receiver<UsageEvent>) {
+    let mut pending = Pending::default();
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Some(event) => {
+                    pending.add(event);
+
+                    if pending.len() >= MAX_PENDING_KEYS {
+                        flush(&db_client, &mut pending).await;
+                    }
+                }
+                None => {
+                    flush(&db_client, &mut pending).await;
+                    break;
+                }
+            },
+            _ = interval.tick() => flush(&db_client, &mut pending).await,
+        }
+    }
+}
+
+async fn flush(db_client: &DBClient, pending: &mut Pending) {
+    if !pending.usages.is_empty() {
+        let usages: Vec<ApiKeyUsage> = pending.usages.drain().map(|(_, usage)| usage).collect();
+
+        if let Err(err) = db_client.record_api_key_usage(&usages).await {
+            println!("🔥 Failed to record API key usage: {:?}", err);
+        }
+    }
+
+    if !pending.rejections.is_empty() {
+        let rejections: Vec<ApiKeyRejectedAddress> = pending.rejections
+            .drain()
+            .map(|((api_key_id, ip), rejected_at)| ApiKeyRejectedAddress {
+                api_key_id,
+                ip_address: ip.to_string(),
+                rejected_at,
+            })
+            .collect();
+
+        if let Err(err) = db_client.record_api_key_rejections(&rejections, REJECTIONS_KEPT).await {
+            println!("🔥 Failed to record API key rejections: {:?}", err);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::net::IpAddr;
+
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+
+// Accepts CIDR ranges as well as single addresses.
+pub fn parse(value: &str) -> Option<IpNet> {
+    let value = value.trim();
+
+    value
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| value.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+pub fn contains(cidrs: &[String], ip: IpAddr) -> bool {
+    let ip = ip.to_canonical();
+
+    cidrs
+        .iter()
+        .filter_map(|cidr| parse(cidr))
+        .any(|network| network.contains(&ip))
+}
+
+// Resolves the client address. `X-Forwarded-For` is only believed when the
+// peer is a trusted proxy, and is read from the right, skipping further trusted
+// proxies, so a client cannot prepend addresses of its own choosing.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[String]) -> IpAddr {
+    if !contains(trusted_proxies, peer) {
+        return peer;
+    }
+
+    let forwarded: Vec<Option<IpAddr>> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|value| value.trim().parse::<IpAddr>().ok())
+        .collect();
+
+    let mut client = peer;
+
+    // An entry that is not an address ends the chain we can vouch for.
+    for ip in forwarded.into_iter().rev().map_while(|ip| ip) {
+        client = ip;
+
+        if !contains(trusted_proxies, ip) {
+            break;
+        }
+    }
+
+    client.to_canonical()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn cidrs(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn forwarded_for(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        for value in values {
+            headers.append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        }
+
+        headers
+    }
+
+    #[test]
+    fn contains_ranges_and_single_addresses() {
+        let allowed = cidrs(&["10.0.0.0/8", " 192.0.2.7 ", "2001:db8::/32"]);
+
+        assert!(contains(&allowed, ip("10.1.2.3")));
+        assert!(contains(&allowed, ip("192.0.2.7")));
+        assert!(!contains(&allowed, ip("192.0.2.8")));
+        assert!(contains(&allowed, ip("2001:db8::1")));
+        assert!(!contains(&allowed, ip("2001:db9::1")));
+    }
+
+    #[test]
+    fn contains_ipv4_mapped_addresses() {
+        assert!(contains(&cidrs(&["10.0.0.0/8"]), ip("::ffff:10.0.0.1")));
+    }
+
+    #[test]
+    fn contains_ignores_invalid_entries() {
+        assert!(!contains(&cidrs(&["not a range", "10.0.0.0/33"]), ip("10.0.0.1")));
+        assert!(!contains(&[], ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn uses_the_peer_when_it_is_not_a_trusted_proxy() {
+        let headers = forwarded_for(&["203.0.113.9"]);
+
+        assert_eq!(client_ip(ip("198.51.100.1"), &headers, &cidrs(&["10.0.0.0/8"])), ip("198.51.100.1"));
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &[]), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn reads_forwarded_for_behind_a_trusted_proxy() {
+        let headers = forwarded_for(&["203.0.113.9"]);
+
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &cidrs(&["10.0.0.0/8"])), ip("203.0.113.9"));
+        assert_eq!(client_ip(ip("10.0.0.1"), &HeaderMap::new(), &cidrs(&["10.0.0.0/8"])), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn ignores_addresses_prepended_by_the_client() {
+        // The client sent `X-Forwarded-For: 192.0.2.1` and the proxy appended
+        // the address it actually saw.
+        let headers = forwarded_for(&["192.0.2.1, 203.0.113.9"]);
+
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &cidrs(&["10.0.0.0/8"])), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn skips_chained_trusted_proxies() {
+        let headers = forwarded_for(&["192.0.2.1, 203.0.113.9", "10.0.0.2"]);
+
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &cidrs(&["10.0.0.0/8"])), ip("203.0.113.9"));
+    }
+
+    #[test]
+    fn stops_at_entries_that_are_not_addresses() {
+        let headers = forwarded_for(&["203.0.113.9, unknown, 10.0.0.2"]);
+
+        assert_eq!(client_ip(ip("10.0.0.1"), &headers, &cidrs(&["10.0.0.0/8"])), ip("10.0.0.2"));
+    }
+}
@@ -0,0 +1,23 @@
+use sha2::{Digest, Sha256};
+use x509_parser::parse_x509_certificate;
+
+// The certificate presented by the client on a TLS connection. It is only
+// attached to requests after rustls has verified it against the client CA.
+#[derive(Debug, Clone)]
+pub struct PeerCertificate {
+    pub fingerprint: String,
+    pub subject: Option<String>,
+}
+
+impl PeerCertificate {
+    pub fn from_der(der: &[u8]) -> Self {
+        let subject = parse_x509_certificate(der)
+            .ok()
+            .map(|(_, certificate)| certificate.subject().to_string());
+
+        PeerCertificate {
+            fingerprint: format!("{:x}", Sha256::digest(der)),
+            subject,
+        }
+    }
+}
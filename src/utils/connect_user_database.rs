@@ -1,6 +1,6 @@
 use sqlx::{postgres::PgConnectOptions, PgPool, Pool, Postgres};
 
-use crate::{error::HttpError, models::DbConnection};
+use crate::{error::HttpError, models::DbConnection, utils::create_table::upgrade_user_specific_table};
 
 pub async fn connect_to_user_database(body: &DbConnection) -> Result<Pool<Postgres>, HttpError> {
     let connect_option = PgConnectOptions::new()
@@ -14,5 +14,7 @@ pub async fn connect_to_user_database(body: &DbConnection) -> Result<Pool<Postgr
         .await
         .map_err(|_| HttpError::server_error("Failed to connect to PostgresSql Database"))?;
 
+    upgrade_user_specific_table(&pool, body).await?;
+
     Ok(pool)
 }
\ No newline at end of file
@@ -0,0 +1,29 @@
+use axum_extra::extract::cookie::Cookie;
+
+use crate::config::CookieConfig;
+
+pub const TOKEN_COOKIE: &str = "token";
+pub const CSRF_COOKIE: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+pub fn build(
+    config: &CookieConfig,
+    name: &'static str,
+    value: impl Into<String>,
+    max_age: time::Duration,
+    http_only: bool,
+) -> Cookie<'static> {
+    let mut cookie = Cookie::build((name, value.into()))
+        .path("/")
+        .max_age(max_age)
+        .http_only(http_only)
+        .secure(config.secure)
+        .same_site(config.same_site)
+        .build();
+
+    if let Some(domain) = &config.domain {
+        cookie.set_domain(domain.clone());
+    }
+
+    cookie
+}
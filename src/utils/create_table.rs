@@ -1,6 +1,91 @@
+use std::{collections::HashSet, sync::Mutex};
+
 use sqlx::{Executor, Pool, Postgres};
 
-use crate::error::HttpError;
+use crate::{error::HttpError, models::DbConnection};
+
+// Changes to the per-user tables made after they were first created. Every
+// statement must be safe to run again, since they run on each new database and
+// on existing databases the first time the server connects to them.
+const SCHEMA_UPGRADES: &[&str] = &[
+    "ALTER TABLE secrets ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ NULL;",
+    // Projects hold environments such as dev, staging and prod, and every
+    // secret lives in exactly one environment.
+    r#"
+        CREATE TABLE IF NOT EXISTS projects (
+            id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+            name VARCHAR(100) NOT NULL UNIQUE,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        );
+    "#,
+    r#"
+        CREATE TABLE IF NOT EXISTS environments (
+            id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+            project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+            name VARCHAR(100) NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            UNIQUE (project_id, name)
+        );
+    "#,
+    // An environment can inherit the secrets of another one in its project.
+    "ALTER TABLE environments ADD COLUMN IF NOT EXISTS parent_id UUID REFERENCES environments(id) ON DELETE SET NULL;",
+    "ALTER TABLE secrets ADD COLUMN IF NOT EXISTS environment_id UUID REFERENCES environments(id);",
+    // Secrets saved before environments existed move to the `default`
+    // environment of the `default` project.
+    r#"
+        INSERT INTO projects (name)
+        SELECT 'default' WHERE EXISTS (SELECT 1 FROM secrets WHERE environment_id IS NULL)
+        ON CONFLICT (name) DO NOTHING;
+    "#,
+    r#"
+        INSERT INTO environments (project_id, name)
+        SELECT id, 'default' FROM projects
+        WHERE name = 'default' AND EXISTS (SELECT 1 FROM secrets WHERE environment_id IS NULL)
+        ON CONFLICT (project_id, name) DO NOTHING;
+    "#,
+    r#"
+        UPDATE secrets
+        SET environment_id = (
+            SELECT environments.id FROM environments
+            JOIN projects ON projects.id = environments.project_id
+            WHERE projects.name = 'default' AND environments.name = 'default'
+        )
+        WHERE environment_id IS NULL;
+    "#,
+    "ALTER TABLE secrets ALTER COLUMN environment_id SET NOT NULL;",
+    // Names became unique among live secrets of an environment; older
+    // duplicates keep their name on the first secret and get the id appended
+    // on the others.
+    r#"
+        UPDATE secrets AS duplicate
+        SET secret_name = LEFT(duplicate.secret_name, 91) || '-' || LEFT(duplicate.id::text, 8)
+        WHERE duplicate.deleted_at IS NULL AND EXISTS (
+            SELECT 1 FROM secrets AS original
+            WHERE original.secret_name = duplicate.secret_name
+                AND original.environment_id = duplicate.environment_id
+                AND original.deleted_at IS NULL
+                AND (original.created_at, original.id) < (duplicate.created_at, duplicate.id)
+        );
+    "#,
+    "DROP INDEX IF EXISTS secrets_secret_name_key;",
+    "CREATE UNIQUE INDEX IF NOT EXISTS secrets_environment_id_secret_name_key ON secrets (environment_id, secret_name) WHERE deleted_at IS NULL;",
+    // Names are slash-delimited paths, which need more room than flat names.
+    "ALTER TABLE secrets ALTER COLUMN secret_name TYPE VARCHAR(512);",
+    "ALTER TABLE secret_versions ALTER COLUMN secret_name TYPE VARCHAR(512);",
+    // Unencrypted metadata that describes a secret and can be filtered on.
+    "ALTER TABLE secrets ADD COLUMN IF NOT EXISTS description TEXT NULL;",
+    "ALTER TABLE secrets ADD COLUMN IF NOT EXISTS owner VARCHAR(255) NULL;",
+    "ALTER TABLE secrets ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';",
+    "ALTER TABLE secrets ADD COLUMN IF NOT EXISTS labels JSONB NOT NULL DEFAULT '{}';",
+    "CREATE INDEX IF NOT EXISTS secrets_tags_idx ON secrets USING GIN (tags);",
+    "CREATE INDEX IF NOT EXISTS secrets_labels_idx ON secrets USING GIN (labels);",
+];
+
+lazy_static::lazy_static! {
+    static ref UPGRADED_DATABASES: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
 
 
 pub async fn create_user_specific_table(
@@ -54,11 +139,100 @@ pub async fn create_user_specific_table(
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    for upgrade in SCHEMA_UPGRADES {
+        transaction
+            .execute(*upgrade)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(())
+
+}
+
+// Brings an existing user database up to date, once per database for the
+// lifetime of the process.
+pub async fn upgrade_user_specific_table(
+    db_pool: &Pool<Postgres>,
+    db_connection: &DbConnection,
+) -> Result<(), HttpError> {
+    let database = format!("{}:{}/{}", db_connection.host, db_connection.port, db_connection.database);
+
+    if UPGRADED_DATABASES.lock().unwrap().contains(&database) {
+        return Ok(());
+    }
+
+    // A database that has no tables yet gets the full schema from
+    // `create_user_specific_table` instead.
+    let has_tables: bool = sqlx::query_scalar("SELECT to_regclass('secrets') IS NOT NULL")
+        .fetch_one(db_pool)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !has_tables {
+        return Ok(());
+    }
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    for upgrade in SCHEMA_UPGRADES {
+        transaction
+            .execute(*upgrade)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+    }
+
     transaction
         .commit()
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
+    UPGRADED_DATABASES.lock().unwrap().insert(database);
+
     Ok(())
+}
+
+pub async fn drop_user_specific_table(
+    db_pool: &Pool<Postgres>,
+) -> Result<(), HttpError> {
+
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    transaction
+        .execute("DROP TABLE IF EXISTS secret_versions;")
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-}
\ No newline at end of file
+    transaction
+        .execute("DROP TABLE IF EXISTS secrets;")
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    transaction
+        .execute("DROP TABLE IF EXISTS environments;")
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    transaction
+        .execute("DROP TABLE IF EXISTS projects;")
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(())
+}
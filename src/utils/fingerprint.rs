@@ -0,0 +1,17 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+// Lets two secret values be compared without revealing either. The HMAC is
+// keyed with the user's encryption key, so a fingerprint cannot be checked
+// against guessed values without that key, and the same value gives different
+// fingerprints for different users.
+pub fn fingerprint(encryption_key: &[u8], value: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(encryption_key)
+        .expect("HMAC accepts keys of any length");
+    mac.update(b"secret-fingerprint:");
+    mac.update(value);
+
+    let digest = mac.finalize().into_bytes();
+
+    digest[..16].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -1,8 +1,6 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
 
-use rand::{rngs::OsRng, Rng};
-
-use crate::models::EncryptionMethod;
+use crate::{models::EncryptionMethod, utils::api_key};
 
 
 pub fn generate_key(method: &EncryptionMethod) -> Vec<u8> {
@@ -32,17 +30,15 @@ pub fn generate_key(method: &EncryptionMethod) -> Vec<u8> {
     }
 }
 
-pub fn generate_api_key() -> String {
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_millis();
-
-    let random_string: String = rand::thread_rng()
-        .sample_iter(&rand::distributions::Alphanumeric)
-        .take(10)
+pub fn generate_api_key(environment: &str) -> String {
+    let random: String = OsRng
+        .sample_iter(&Alphanumeric)
+        .take(api_key::RANDOM_LENGTH)
         .map(char::from)
         .collect();
 
-    format!("{}{}", timestamp, random_string)
-}
\ No newline at end of file
+    let body = format!("{}_{}_{}", api_key::KEY_PREFIX, environment, random);
+    let checksum = api_key::checksum(&body);
+
+    format!("{}_{}", body, checksum)
+}
@@ -0,0 +1,185 @@
+use ldap3::{ldap_escape, LdapConnAsync, Scope, SearchEntry};
+
+use crate::{config::LdapConfig, error::{ErrorMessage, HttpError}, models::UserRole};
+
+// Issuer recorded for directory identities, whose subject is the entry's DN.
+pub const IDENTITY_ISSUER: &str = "ldap";
+
+#[derive(Debug, Clone)]
+pub struct DirectoryUser {
+    pub dn: String,
+    pub name: Option<String>,
+    pub groups: Vec<String>,
+}
+
+/// Looks the user up with the service account and then binds as them.
+/// Returns `Ok(None)` when the directory does not know the email, so the
+/// caller can fall back to the local password.
+pub async fn authenticate(
+    config: &LdapConfig,
+    email: &str,
+    password: &str,
+) -> Result<Option<DirectoryUser>, HttpError> {
+    // An empty password turns a simple bind into an anonymous bind, which
+    // most servers accept.
+    if password.is_empty() {
+        return Err(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()));
+    }
+
+    let (conn, mut ldap) = LdapConnAsync::new(&config.url)
+        .await
+        .map_err(|e| HttpError::server_error(format!("Failed to connect to LDAP server: {}", e)))?;
+    ldap3::drive!(conn);
+
+    if let (Some(bind_dn), Some(bind_password)) = (&config.bind_dn, &config.bind_password) {
+        ldap.simple_bind(bind_dn, bind_password)
+            .await
+            .and_then(|result| result.success())
+            .map_err(|e| HttpError::server_error(format!("LDAP service bind failed: {}", e)))?;
+    }
+
+    let filter = config.user_filter.replace("{email}", &ldap_escape(email));
+
+    let (entries, _) = ldap
+        .search(
+            &config.user_base_dn,
+            Scope::Subtree,
+            &filter,
+            vec![config.name_attribute.as_str(), config.group_attribute.as_str()],
+        )
+        .await
+        .and_then(|result| result.success())
+        .map_err(|e| HttpError::server_error(format!("LDAP search failed: {}", e)))?;
+
+    let entry = match entries.into_iter().next() {
+        Some(entry) => SearchEntry::construct(entry),
+        None => {
+            let _ = ldap.unbind().await;
+            return Ok(None);
+        }
+    };
+
+    let user_bind = ldap.simple_bind(&entry.dn, password)
+        .await
+        .and_then(|result| result.success());
+
+    let _ = ldap.unbind().await;
+
+    if user_bind.is_err() {
+        return Err(HttpError::bad_request(ErrorMessage::WrongCredentials.to_string()));
+    }
+
+    Ok(Some(DirectoryUser {
+        dn: entry.dn,
+        name: entry.attrs.get(&config.name_attribute).and_then(|values| values.first().cloned()),
+        groups: entry.attrs.get(&config.group_attribute).cloned().unwrap_or_default(),
+    }))
+}
+
+/// Picks the highest role granted by any of the user's groups.
+pub fn map_role(config: &LdapConfig, groups: &[String]) -> UserRole {
+    groups
+        .iter()
+        .filter_map(|group| {
+            let group = group.to_lowercase();
+            config.group_roles
+                .iter()
+                .find(|(mapped_group, _)| *mapped_group == group)
+                .map(|(_, role)| *role)
+        })
+        .fold(UserRole::User, |highest, role| if role > highest { role } else { highest })
+}
+
+#[cfg(test)]
+mod tests {
+    use ldap3::LdapConnAsync;
+
+    use super::*;
+
+    fn config(url: &str) -> LdapConfig {
+        LdapConfig {
+            url: url.to_string(),
+            bind_dn: None,
+            bind_password: None,
+            user_base_dn: "ou=people,dc=example,dc=org".to_string(),
+            user_filter: "(mail={email})".to_string(),
+            name_attribute: "cn".to_string(),
+            group_attribute: "memberOf".to_string(),
+            group_roles: vec![
+                ("cn=admins,ou=groups,dc=example,dc=org".to_string(), UserRole::Admin),
+                ("cn=staff,ou=groups,dc=example,dc=org".to_string(), UserRole::User),
+            ],
+        }
+    }
+
+    #[test]
+    fn maps_highest_role_of_any_group() {
+        let config = config("ldap://localhost");
+
+        let groups = vec![
+            "cn=staff,ou=groups,dc=example,dc=org".to_string(),
+            "CN=Admins,OU=Groups,DC=example,DC=org".to_string(),
+        ];
+
+        assert_eq!(map_role(&config, &groups), UserRole::Admin);
+    }
+
+    #[test]
+    fn maps_unknown_groups_to_user() {
+        let config = config("ldap://localhost");
+
+        assert_eq!(map_role(&config, &["cn=others,dc=example,dc=org".to_string()]), UserRole::User);
+        assert_eq!(map_role(&config, &[]), UserRole::User);
+    }
+
+    // Runs against an OpenLDAP server started as described in the README, e.g.
+    // `LDAP_TEST_URL=ldap://localhost:389 cargo test -- --ignored`. Groups are
+    // kept in `employeeType` on the user entry, so the test does not depend on
+    // the server's memberOf overlay.
+    #[tokio::test]
+    #[ignore = "needs an OpenLDAP server at LDAP_TEST_URL"]
+    async fn authenticates_against_openldap() {
+        let url = std::env::var("LDAP_TEST_URL").expect("LDAP_TEST_URL must be set");
+        let admin_dn = std::env::var("LDAP_TEST_ADMIN_DN").unwrap_or_else(|_| "cn=admin,dc=example,dc=org".to_string());
+        let admin_password = std::env::var("LDAP_TEST_ADMIN_PASSWORD").unwrap_or_else(|_| "admin".to_string());
+
+        let (conn, mut ldap) = LdapConnAsync::new(&url).await.unwrap();
+        ldap3::drive!(conn);
+        ldap.simple_bind(&admin_dn, &admin_password).await.unwrap().success().unwrap();
+
+        let people = vec![("objectClass", ["organizationalUnit"].into()), ("ou", ["people"].into())];
+        let _ = ldap.add("ou=people,dc=example,dc=org", people).await;
+
+        let _ = ldap.delete("uid=ada,ou=people,dc=example,dc=org").await;
+        ldap.add("uid=ada,ou=people,dc=example,dc=org", vec![
+            ("objectClass", ["inetOrgPerson"].into()),
+            ("uid", ["ada"].into()),
+            ("cn", ["Ada Lovelace"].into()),
+            ("sn", ["Lovelace"].into()),
+            ("mail", ["ada@example.org"].into()),
+            ("userPassword", ["analytical"].into()),
+            ("employeeType", ["cn=admins,ou=groups,dc=example,dc=org"].into()),
+        ]).await.unwrap().success().unwrap();
+        let _ = ldap.unbind().await;
+
+        let config = LdapConfig {
+            bind_dn: Some(admin_dn),
+            bind_password: Some(admin_password),
+            group_attribute: "employeeType".to_string(),
+            ..config(&url)
+        };
+
+        let directory_user = authenticate(&config, "ada@example.org", "analytical").await.unwrap().unwrap();
+        assert_eq!(directory_user.dn, "uid=ada,ou=people,dc=example,dc=org");
+        assert_eq!(directory_user.name.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(map_role(&config, &directory_user.groups), UserRole::Admin);
+
+        let wrong_password = authenticate(&config, "ada@example.org", "difference").await.unwrap_err();
+        assert_eq!(wrong_password.message, ErrorMessage::WrongCredentials.to_string());
+
+        assert!(authenticate(&config, "nobody@example.org", "analytical").await.unwrap().is_none());
+
+        let unreachable = authenticate(&LdapConfig { url: "ldap://127.0.0.1:1".to_string(), ..config }, "ada@example.org", "analytical").await.unwrap_err();
+        assert_eq!(unreachable.status, axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -4,4 +4,15 @@ pub mod generate_key;
 pub mod encrypt;
 pub mod decrypt;
 pub mod connect_user_database;
-pub mod create_table;
\ No newline at end of file
+pub mod create_table;
+pub mod oidc;
+pub mod ldap;
+pub mod password_policy;
+pub mod signing_keys;
+pub mod client_cert;
+pub mod cookie;
+pub mod workload_identity;
+pub mod cidr;
+pub mod api_key;
+pub mod api_key_usage;
+pub mod secret_path;pub mod fingerprint;
@@ -0,0 +1,379 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{config::OidcConfig, error::HttpError};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenResponse {
+    pub id_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub name: Option<String>,
+    pub nonce: Option<String>,
+}
+
+impl IdTokenClaims {
+    // Accounts are matched by email, so an address the provider has not
+    // verified could take over someone else's account. Providers that never
+    // send `email_verified` but only issue addresses they own can be trusted
+    // with OIDC_TRUST_EMAIL.
+    pub fn has_trusted_email(&self, config: &OidcConfig) -> bool {
+        self.email_verified == Some(true) || (config.trust_email && self.email_verified.is_none())
+    }
+}
+
+pub fn random_token(length: usize) -> String {
+    OsRng
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+pub async fn discover(issuer_url: &str) -> Result<ProviderMetadata, HttpError> {
+    let url = format!("{}/.well-known/openid-configuration", issuer_url);
+
+    let metadata = reqwest::get(&url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| HttpError::server_error(format!("OIDC discovery failed: {}", e)))?
+        .json::<ProviderMetadata>()
+        .await
+        .map_err(|e| HttpError::server_error(format!("Invalid OIDC discovery document: {}", e)))?;
+
+    if metadata.issuer.trim_end_matches('/') != issuer_url {
+        return Err(HttpError::server_error("OIDC discovery document issuer does not match the configured issuer"));
+    }
+
+    Ok(metadata)
+}
+
+pub async fn fetch_jwks(jwks_uri: &str) -> Result<JwkSet, HttpError> {
+    reqwest::get(jwks_uri)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| HttpError::server_error(format!("Failed to fetch JWKS: {}", e)))?
+        .json::<JwkSet>()
+        .await
+        .map_err(|e| HttpError::server_error(format!("Invalid JWKS document: {}", e)))
+}
+
+pub fn authorization_url(
+    config: &OidcConfig,
+    metadata: &ProviderMetadata,
+    state: &str,
+    nonce: &str,
+    code_challenge: &str,
+) -> Result<String, HttpError> {
+    let url = reqwest::Url::parse_with_params(
+        &metadata.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", config.client_id.as_str()),
+            ("redirect_uri", config.redirect_url.as_str()),
+            ("scope", config.scopes.as_str()),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", code_challenge),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|e| HttpError::server_error(format!("Invalid authorization endpoint: {}", e)))?;
+
+    Ok(url.to_string())
+}
+
+pub async fn exchange_code(
+    config: &OidcConfig,
+    metadata: &ProviderMetadata,
+    code: &str,
+    code_verifier: &str,
+) -> Result<TokenResponse, HttpError> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", config.redirect_url.as_str()),
+        ("client_id", config.client_id.as_str()),
+        ("code_verifier", code_verifier),
+    ];
+
+    if let Some(client_secret) = &config.client_secret {
+        form.push(("client_secret", client_secret.as_str()));
+    }
+
+    reqwest::Client::new()
+        .post(&metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| HttpError::unauthorized(format!("OIDC code exchange failed: {}", e)))?
+        .json::<TokenResponse>()
+        .await
+        .map_err(|e| HttpError::server_error(format!("Invalid OIDC token response: {}", e)))
+}
+
+// Verifies the signature against the issuer's JWKS along with `iss`, `aud`
+// and `exp`. Symmetric algorithms are refused since the key set is public.
+pub fn verify_jwt<T: DeserializeOwned>(
+    token: &str,
+    jwks: &JwkSet,
+    issuer: &str,
+    audience: &str,
+) -> Option<T> {
+    let header = decode_header(token).ok()?;
+
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return None;
+    }
+
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }?;
+
+    let decoding_key = DecodingKey::from_jwk(jwk).ok()?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[audience]);
+
+    decode::<T>(token, &decoding_key, &validation)
+        .ok()
+        .map(|token| token.claims)
+}
+
+pub fn validate_id_token(
+    id_token: &str,
+    jwks: &JwkSet,
+    issuer: &str,
+    audience: &str,
+    expected_nonce: &str,
+) -> Result<IdTokenClaims, HttpError> {
+    let invalid = || HttpError::unauthorized("ID token is invalid");
+
+    let claims = verify_jwt::<IdTokenClaims>(id_token, jwks, issuer, audience)
+        .ok_or_else(invalid)?;
+
+    if claims.nonce.as_deref() != Some(expected_nonce) {
+        return Err(invalid());
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::{http::StatusCode, routing::{get, post}, Form, Json, Router};
+    use ed25519_dalek::pkcs8::{spki::der::pem::LineEnding, EncodePrivateKey};
+    use jsonwebtoken::{encode, jwk::{AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, OctetKeyPairParameters, OctetKeyPairType}, EncodingKey, Header};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const CLIENT_ID: &str = "secret-backend";
+    const CODE: &str = "authorization-code";
+    const CODE_VERIFIER: &str = "code-verifier";
+    const NONCE: &str = "nonce";
+
+    struct MockIdp {
+        issuer: String,
+        signing_key: EncodingKey,
+    }
+
+    impl MockIdp {
+        fn id_token(&self, claims: Value) -> String {
+            let mut header = Header::new(Algorithm::EdDSA);
+            header.kid = Some("idp-key".to_string());
+
+            encode(&header, &claims, &self.signing_key).unwrap()
+        }
+
+        fn claims(&self) -> Value {
+            json!({
+                "iss": self.issuer,
+                "aud": CLIENT_ID,
+                "exp": chrono::Utc::now().timestamp() + 300,
+                "sub": "user-1",
+                "email": "ada@example.org",
+                "email_verified": true,
+                "name": "Ada Lovelace",
+                "nonce": NONCE,
+            })
+        }
+    }
+
+    // Serves discovery, JWKS and a token endpoint that hands out `id_token`
+    // for the expected code and PKCE verifier.
+    async fn start_idp(id_token: impl Fn(&MockIdp) -> String) -> (MockIdp, OidcConfig) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let private_key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        let pem = private_key.to_pkcs8_pem(LineEnding::LF).unwrap();
+
+        let idp = MockIdp {
+            issuer: issuer.clone(),
+            signing_key: EncodingKey::from_ed_pem(pem.as_bytes()).unwrap(),
+        };
+
+        let jwks = JwkSet {
+            keys: vec![Jwk {
+                common: CommonParameters {
+                    key_id: Some("idp-key".to_string()),
+                    ..Default::default()
+                },
+                algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(private_key.verifying_key().to_bytes()),
+                }),
+            }],
+        };
+
+        let discovery = json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+            "jwks_uri": format!("{}/jwks", issuer),
+        });
+
+        let id_token = id_token(&idp);
+
+        let app = Router::new()
+            .route("/.well-known/openid-configuration", get(move || async move { Json(discovery) }))
+            .route("/jwks", get(move || async move { Json(jwks) }))
+            .route("/token", post(move |Form(form): Form<HashMap<String, String>>| async move {
+                let valid = form.get("grant_type").map(String::as_str) == Some("authorization_code")
+                    && form.get("code").map(String::as_str) == Some(CODE)
+                    && form.get("code_verifier").map(String::as_str) == Some(CODE_VERIFIER)
+                    && form.get("client_id").map(String::as_str) == Some(CLIENT_ID);
+
+                match valid {
+                    true => Ok(Json(json!({ "id_token": id_token, "token_type": "Bearer" }))),
+                    false => Err(StatusCode::BAD_REQUEST),
+                }
+            }));
+
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config = OidcConfig {
+            issuer_url: issuer,
+            client_id: CLIENT_ID.to_string(),
+            client_secret: None,
+            redirect_url: "http://localhost:8000/api/auth/oidc/callback".to_string(),
+            scopes: "openid email profile".to_string(),
+            trust_email: false,
+        };
+
+        (idp, config)
+    }
+
+    async fn log_in(config: &OidcConfig, code: &str) -> Result<IdTokenClaims, HttpError> {
+        let metadata = discover(&config.issuer_url).await?;
+        let token_response = exchange_code(config, &metadata, code, CODE_VERIFIER).await?;
+        let jwks = fetch_jwks(&metadata.jwks_uri).await?;
+
+        validate_id_token(&token_response.id_token, &jwks, &metadata.issuer, &config.client_id, NONCE)
+    }
+
+    #[tokio::test]
+    async fn completes_the_code_flow_against_a_mock_idp() {
+        let (_, config) = start_idp(|idp| idp.id_token(idp.claims())).await;
+
+        let claims = log_in(&config, CODE).await.unwrap();
+
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.email.as_deref(), Some("ada@example.org"));
+        assert!(claims.has_trusted_email(&config));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_wrong_code() {
+        let (_, config) = start_idp(|idp| idp.id_token(idp.claims())).await;
+
+        let err = log_in(&config, "stolen-code").await.unwrap_err();
+
+        assert_eq!(err.status, axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_mismatched_discovery_issuer() {
+        let (_, config) = start_idp(|idp| idp.id_token(idp.claims())).await;
+
+        let err = discover(&format!("{}/realms/other", config.issuer_url)).await.unwrap_err();
+
+        assert_eq!(err.status, axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn rejects_tokens_with_the_wrong_nonce_audience_or_issuer() {
+        let cases: [fn(&mut Value); 4] = [
+            |claims| claims["nonce"] = json!("replayed"),
+            |claims| claims["aud"] = json!("another-client"),
+            |claims| claims["iss"] = json!("https://evil.example.com"),
+            |claims| claims["exp"] = json!(chrono::Utc::now().timestamp() - 3600),
+        ];
+
+        for change in cases {
+            let (_, config) = start_idp(move |idp| {
+                let mut claims = idp.claims();
+                change(&mut claims);
+                idp.id_token(claims)
+            }).await;
+
+            assert!(log_in(&config, CODE).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_tokens_signed_with_a_shared_secret() {
+        let (_, config) = start_idp(|idp| {
+            encode(&Header::new(Algorithm::HS256), &idp.claims(), &EncodingKey::from_secret(b"public")).unwrap()
+        }).await;
+
+        assert!(log_in(&config, CODE).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn trusts_only_verified_emails_unless_configured() {
+        let (_, config) = start_idp(|idp| {
+            let mut claims = idp.claims();
+            claims.as_object_mut().unwrap().remove("email_verified");
+            idp.id_token(claims)
+        }).await;
+
+        let claims = log_in(&config, CODE).await.unwrap();
+
+        assert!(!claims.has_trusted_email(&config));
+        assert!(claims.has_trusted_email(&OidcConfig { trust_email: true, ..config.clone() }));
+
+        let unverified = IdTokenClaims { email_verified: Some(false), ..claims };
+
+        assert!(!unverified.has_trusted_email(&config));
+        assert!(!unverified.has_trusted_email(&OidcConfig { trust_email: true, ..config }));
+    }
+}
@@ -1,25 +1,69 @@
 use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, SaltString}, 
-    Argon2, PasswordVerifier
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, SaltString},
+    Algorithm, Argon2, Params, ParamsBuilder, PasswordVerifier, Version
 };
 
-use crate::error::ErrorMessage;
+use crate::{config::PasswordHashingConfig, error::ErrorMessage};
 
-const MAX_PASSWORD_LENGTH: usize = 64;
+// Longer input is refused before it reaches Argon2, so oversized passwords
+// cannot be used to tie up the server. The policy's length rules only apply
+// when a password is set or changed: tightening them must not lock anyone out
+// of the password they already have.
+pub const MAX_LENGTH: usize = 1024;
 
-pub fn hash(password: impl Into<String>) -> Result<String, ErrorMessage> {
+// Peppered hashes carry the pepper id in the PHC `keyid` parameter, so hashes
+// created before a pepper was configured, or under a retired pepper, can still
+// be verified.
+fn hasher<'a>(config: &'a PasswordHashingConfig, keyid: &[u8]) -> Result<Argon2<'a>, ErrorMessage> {
+    let mut builder = ParamsBuilder::new();
+    builder
+        .m_cost(config.memory_kib)
+        .t_cost(config.iterations)
+        .p_cost(config.parallelism);
+
+    if !keyid.is_empty() {
+        builder.keyid(keyid.try_into().map_err(|_| ErrorMessage::HashingError)?);
+    }
+
+    let params = builder.build().map_err(|_| ErrorMessage::HashingError)?;
+
+    if keyid.is_empty() {
+        return Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params));
+    }
+
+    let pepper = config.pepper.as_ref()
+        .filter(|_| keyid == config.pepper_id.as_bytes())
+        .or_else(|| {
+            std::str::from_utf8(keyid)
+                .ok()
+                .and_then(|keyid| config.retired_peppers.get(keyid))
+        })
+        .ok_or(ErrorMessage::InvalidHashFormat)?;
+
+    Argon2::new_with_secret(pepper.as_bytes(), Algorithm::Argon2id, Version::V0x13, params)
+        .map_err(|_| ErrorMessage::HashingError)
+}
+
+fn current_keyid(config: &PasswordHashingConfig) -> &[u8] {
+    match config.pepper {
+        Some(_) => config.pepper_id.as_bytes(),
+        None => &[],
+    }
+}
+
+pub fn hash(config: &PasswordHashingConfig, password: impl Into<String>) -> Result<String, ErrorMessage> {
     let password = password.into();
 
     if password.is_empty() {
         return Err(ErrorMessage::EmptyPassword);
     }
 
-    if password.len() > MAX_PASSWORD_LENGTH {
-        return Err(ErrorMessage::ExceededMaxPasswordLength(MAX_PASSWORD_LENGTH));
+    if password.chars().count() > MAX_LENGTH {
+        return Err(ErrorMessage::ExceededMaxPasswordLength(MAX_LENGTH));
     }
 
     let salt = SaltString::generate(&mut OsRng);
-    let hashed_password = Argon2::default()
+    let hashed_password = hasher(config, current_keyid(config))?
         .hash_password(password.as_bytes(), &salt)
         .map_err(|_| ErrorMessage::HashingError)?
         .to_string();
@@ -27,22 +71,129 @@ pub fn hash(password: impl Into<String>) -> Result<String, ErrorMessage> {
     Ok(hashed_password)
 }
 
-pub fn compare(password: &str, hashed_password: &str) -> Result<bool, ErrorMessage> {
+pub fn compare(config: &PasswordHashingConfig, password: &str, hashed_password: &str) -> Result<bool, ErrorMessage> {
     if password.is_empty() {
         return Err(ErrorMessage::EmptyPassword);
     }
 
-    if password.len() > MAX_PASSWORD_LENGTH {
-        return Err(ErrorMessage::ExceededMaxPasswordLength(MAX_PASSWORD_LENGTH));
+    if password.chars().count() > MAX_LENGTH {
+        return Err(ErrorMessage::ExceededMaxPasswordLength(MAX_LENGTH));
     }
 
-    let pares_hash = 
+    let pares_hash =
         PasswordHash::new(hashed_password).
         map_err(|_| ErrorMessage::InvalidHashFormat)?;
 
-    let password_matches = Argon2::default()
+    let params = Params::try_from(&pares_hash)
+        .map_err(|_| ErrorMessage::InvalidHashFormat)?;
+
+    // Verification uses the algorithm and cost parameters stored in the hash
+    // itself; only the pepper is taken from the configuration.
+    let password_matches = hasher(config, params.keyid())?
         .verify_password(password.as_bytes(), &pares_hash)
-        .map_or(false, |_| true);
+        .is_ok();
 
     Ok(password_matches)
-}
\ No newline at end of file
+}
+
+pub fn needs_rehash(config: &PasswordHashingConfig, hashed_password: &str) -> bool {
+    let Ok(pares_hash) = PasswordHash::new(hashed_password) else {
+        return false;
+    };
+
+    let Ok(params) = Params::try_from(&pares_hash) else {
+        return false;
+    };
+
+    pares_hash.algorithm != Algorithm::Argon2id.ident()
+        || pares_hash.version != Some(Version::V0x13.into())
+        || params.m_cost() != config.memory_kib
+        || params.t_cost() != config.iterations
+        || params.p_cost() != config.parallelism
+        || params.keyid() != current_keyid(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::utils::password_policy::PASSWORD_POLICY;
+
+    use super::*;
+
+    fn config(pepper: Option<(&str, &str)>, retired: &[(&str, &str)]) -> PasswordHashingConfig {
+        PasswordHashingConfig {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+            pepper: pepper.map(|(_, pepper)| pepper.to_string()),
+            pepper_id: pepper.map(|(pepper_id, _)| pepper_id).unwrap_or("pepper1").to_string(),
+            retired_peppers: retired.iter()
+                .map(|(pepper_id, pepper)| (pepper_id.to_string(), pepper.to_string()))
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn verifies_its_own_hashes() {
+        let config = config(Some(("pepper1", "first")), &[]);
+        let hashed = hash(&config, "correct horse").unwrap();
+
+        assert!(compare(&config, "correct horse", &hashed).unwrap());
+        assert!(!compare(&config, "wrong horse", &hashed).unwrap());
+        assert!(!needs_rehash(&config, &hashed));
+    }
+
+    #[test]
+    fn keeps_verifying_hashes_under_a_retired_pepper() {
+        let old = config(Some(("pepper1", "first")), &[]);
+        let hashed = hash(&old, "correct horse").unwrap();
+
+        let rotated = config(Some(("pepper2", "second")), &[("pepper1", "first")]);
+
+        assert!(compare(&rotated, "correct horse", &hashed).unwrap());
+        assert!(needs_rehash(&rotated, &hashed));
+
+        let rehashed = hash(&rotated, "correct horse").unwrap();
+        assert!(!needs_rehash(&rotated, &rehashed));
+    }
+
+    #[test]
+    fn rejects_hashes_under_an_unknown_pepper() {
+        let hashed = hash(&config(Some(("pepper1", "first")), &[]), "correct horse").unwrap();
+
+        let rotated = config(Some(("pepper2", "second")), &[]);
+
+        assert!(compare(&rotated, "correct horse", &hashed).is_err());
+    }
+
+    #[test]
+    fn verifies_unpeppered_hashes_once_a_pepper_is_added() {
+        let hashed = hash(&config(None, &[]), "correct horse").unwrap();
+
+        let peppered = config(Some(("pepper1", "first")), &[]);
+
+        assert!(compare(&peppered, "correct horse", &hashed).unwrap());
+        assert!(needs_rehash(&peppered, &hashed));
+    }
+
+    #[test]
+    fn rehashes_when_cost_parameters_change() {
+        let config = config(None, &[]);
+        let hashed = hash(&config, "correct horse").unwrap();
+
+        let stronger = PasswordHashingConfig { iterations: 2, ..config };
+
+        assert!(needs_rehash(&stronger, &hashed));
+    }
+
+    #[test]
+    fn verifies_passwords_longer_than_the_policy_allows() {
+        let config = config(None, &[]);
+        let long_password = "a".repeat(PASSWORD_POLICY.max_length + 1);
+        let hashed = hash(&config, long_password.as_str()).unwrap();
+
+        assert!(compare(&config, &long_password, &hashed).unwrap());
+        assert!(compare(&config, &"a".repeat(MAX_LENGTH + 1), &hashed).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+use std::{fs::File, io::{self, BufRead, BufReader, Seek, SeekFrom}};
+
+use lazy_static::lazy_static;
+use sha1::{Digest, Sha1};
+
+use crate::utils::password;
+
+lazy_static! {
+    pub static ref PASSWORD_POLICY: PasswordPolicy = PasswordPolicy::from_env();
+}
+
+#[derive(Debug)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub min_strength: u8,
+    pub history_size: i64,
+    breached_hashes: Option<BreachedHashes>,
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.parse::<T>().unwrap_or_else(|_| panic!("{} has an invalid value", name)))
+        .unwrap_or(default)
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Self {
+        let breached_hashes = std::env::var("PASSWORD_BREACHED_HASHES_FILE")
+            .ok()
+            .map(|path| BreachedHashes::open(&path));
+
+        let max_length = env_or("PASSWORD_MAX_LENGTH", 64);
+
+        if max_length > password::MAX_LENGTH {
+            panic!("PASSWORD_MAX_LENGTH must not be more than {}", password::MAX_LENGTH);
+        }
+
+        PasswordPolicy {
+            min_length: env_or("PASSWORD_MIN_LENGTH", 6),
+            max_length,
+            require_lowercase: env_or("PASSWORD_REQUIRE_LOWERCASE", false),
+            require_uppercase: env_or("PASSWORD_REQUIRE_UPPERCASE", false),
+            require_digit: env_or("PASSWORD_REQUIRE_DIGIT", false),
+            require_symbol: env_or("PASSWORD_REQUIRE_SYMBOL", false),
+            min_strength: env_or("PASSWORD_MIN_STRENGTH", 0),
+            history_size: env_or("PASSWORD_HISTORY_SIZE", 0),
+            breached_hashes,
+        }
+    }
+
+    pub fn check(&self, password: &str) -> Vec<String> {
+        let mut reasons = Vec::new();
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            reasons.push(format!("must be at least {} characters", self.min_length));
+        }
+
+        if length > self.max_length {
+            reasons.push(format!("must not be more than {} characters", self.max_length));
+        }
+
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            reasons.push("must contain a lowercase letter".to_string());
+        }
+
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            reasons.push("must contain an uppercase letter".to_string());
+        }
+
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            reasons.push("must contain a digit".to_string());
+        }
+
+        if self.require_symbol && password.chars().all(|c| c.is_alphanumeric()) {
+            reasons.push("must contain a symbol".to_string());
+        }
+
+        if self.min_strength > 0 && length <= self.max_length {
+            let score: u8 = zxcvbn::zxcvbn(password, &[]).score().into();
+            if score < self.min_strength {
+                reasons.push(format!("is too weak (strength {} of 4, at least {} required)", score, self.min_strength));
+            }
+        }
+
+        if self.is_breached(password) {
+            reasons.push("has appeared in a data breach".to_string());
+        }
+
+        reasons
+    }
+
+    pub fn is_breached(&self, password: &str) -> bool {
+        let Some(breached_hashes) = &self.breached_hashes else {
+            return false;
+        };
+
+        match breached_hashes.contains(&Sha1::digest(password.as_bytes())) {
+            Ok(breached) => breached,
+            Err(err) => {
+                println!("🔥 Failed to search PASSWORD_BREACHED_HASHES_FILE: {:?}", err);
+                false
+            }
+        }
+    }
+}
+
+// SHA-1 hashes one per line, in the `HASH` or `HASH:COUNT` format of the Have
+// I Been Pwned downloads, sorted by hash. The file is binary searched on disk
+// for every check, so even the full list costs no memory.
+#[derive(Debug)]
+struct BreachedHashes {
+    path: String,
+}
+
+impl BreachedHashes {
+    fn open(path: &str) -> Self {
+        File::open(path)
+            .unwrap_or_else(|e| panic!("Failed to read PASSWORD_BREACHED_HASHES_FILE: {}", e));
+
+        BreachedHashes { path: path.to_string() }
+    }
+
+    fn contains(&self, digest: &[u8]) -> io::Result<bool> {
+        let target: Vec<u8> = digest.iter()
+            .flat_map(|byte| format!("{:02X}", byte).into_bytes())
+            .collect();
+
+        let mut file = BufReader::new(File::open(&self.path)?);
+        let (mut low, mut high) = (0, file.get_ref().metadata()?.len());
+
+        // Finds the first offset whose line does not sort before the target.
+        while low < high {
+            let middle = low + (high - low) / 2;
+
+            match hash_at(&mut file, middle)? {
+                Some(hash) if hash < target => low = middle + 1,
+                _ => high = middle,
+            }
+        }
+
+        Ok(hash_at(&mut file, low)?.as_deref() == Some(target.as_slice()))
+    }
+}
+
+// The uppercased hash of the first line starting at or after `offset`. Lines
+// are handled as bytes, so a malformed line can only fail to match.
+fn hash_at(file: &mut BufReader<File>, offset: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+
+    if offset > 0 {
+        file.seek(SeekFrom::Start(offset - 1))?;
+        file.read_until(b'\n', &mut line)?;
+        line.clear();
+    } else {
+        file.seek(SeekFrom::Start(0))?;
+    }
+
+    if file.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let hash = line.split(|&b| b == b':').next().unwrap_or_default().trim_ascii();
+
+    Ok(Some(hash.to_ascii_uppercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 16,
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            min_strength: 0,
+            history_size: 0,
+            breached_hashes: None,
+        }
+    }
+
+    fn sha1_hex(password: &str) -> String {
+        format!("{:X}", Sha1::digest(password.as_bytes()))
+    }
+
+    fn breach_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("breached-{}-{}.txt", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn accepts_password_meeting_every_rule() {
+        assert!(policy().check("Abcdef1!").is_empty());
+    }
+
+    #[test]
+    fn reports_every_failed_rule() {
+        let reasons = policy().check("abc");
+
+        assert_eq!(reasons, vec![
+            "must be at least 8 characters".to_string(),
+            "must contain an uppercase letter".to_string(),
+            "must contain a digit".to_string(),
+            "must contain a symbol".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn counts_length_in_characters() {
+        let policy = PasswordPolicy { min_length: 4, max_length: 4, ..policy() };
+
+        assert!(!policy.check("Éé1!").iter().any(|reason| reason.contains("characters")));
+        assert!(policy.check("Éé1!x").contains(&"must not be more than 4 characters".to_string()));
+    }
+
+    #[test]
+    fn rejects_weak_password_by_strength() {
+        let policy = PasswordPolicy { min_strength: 3, ..policy() };
+
+        assert!(policy.check("Password1!").iter().any(|reason| reason.starts_with("is too weak")));
+    }
+
+    #[test]
+    fn finds_breached_hashes_in_sorted_file() {
+        let mut hashes: Vec<String> = ["password", "123456", "qwerty", "letmein", "dragon"]
+            .iter()
+            .map(|password| format!("{}:42", sha1_hex(password)))
+            .collect();
+        hashes.sort();
+
+        let path = breach_file("sorted", &format!("{}\n", hashes.join("\r\n")));
+        let policy = PasswordPolicy { breached_hashes: Some(BreachedHashes::open(path.to_str().unwrap())), ..policy() };
+
+        for password in ["password", "123456", "qwerty", "letmein", "dragon"] {
+            assert!(policy.is_breached(password), "{} should be breached", password);
+        }
+        assert!(!policy.is_breached("Abcdef1!"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn matches_lowercase_hashes() {
+        let path = breach_file("lowercase", &sha1_hex("hunter2").to_lowercase());
+        let policy = PasswordPolicy { breached_hashes: Some(BreachedHashes::open(path.to_str().unwrap())), ..policy() };
+
+        assert!(policy.is_breached("hunter2"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn tolerates_malformed_lines() {
+        let path = breach_file("malformed", &format!("00\n\n{}\nzzzz\né\u{1F600}\n", sha1_hex("hunter2")));
+        let policy = PasswordPolicy { breached_hashes: Some(BreachedHashes::open(path.to_str().unwrap())), ..policy() };
+
+        assert!(policy.is_breached("hunter2"));
+        assert!(!policy.is_breached("hunter3"));
+
+        fs::remove_file(path).unwrap();
+    }
+}
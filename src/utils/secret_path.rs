@@ -0,0 +1,115 @@
+// Secret names are slash-delimited paths such as `payments/prod/db/password`.
+// Every segment before the last one is a folder; folders exist only as long as
+// some secret lives beneath them.
+pub const MAX_LENGTH: usize = 512;
+
+pub fn is_valid(path: &str) -> bool {
+    path.len() <= MAX_LENGTH
+        && path.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && segment.trim() == segment
+                && !segment.chars().any(char::is_control)
+        })
+}
+
+// Accepts folders written with or without leading and trailing slashes; the
+// root folder is the empty string.
+pub fn normalize_folder(folder: &str) -> String {
+    folder.trim_matches('/').to_string()
+}
+
+// The prefix every path inside `folder` starts with.
+pub fn folder_prefix(folder: &str) -> String {
+    if folder.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", folder)
+    }
+}
+
+// API keys can be limited to paths: a pattern names one secret, or with a
+// trailing `/*` every secret at any depth inside a folder, as in
+// `payments/prod/*`.
+pub fn is_valid_pattern(pattern: &str) -> bool {
+    is_valid(pattern.strip_suffix("/*").unwrap_or(pattern))
+}
+
+pub fn matches(pattern: &str, path: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(folder) => path.starts_with(&folder_prefix(folder)),
+        None => path == pattern,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_plain_names_and_paths() {
+        assert!(is_valid("API_KEY"));
+        assert!(is_valid("payments/prod/db/password"));
+        assert!(is_valid("team a/db password"));
+        assert!(is_valid("config/.env"));
+        assert!(is_valid("café/clé"));
+    }
+
+    #[test]
+    fn rejects_empty_segments() {
+        assert!(!is_valid(""));
+        assert!(!is_valid("/payments"));
+        assert!(!is_valid("payments/"));
+        assert!(!is_valid("payments//db"));
+    }
+
+    #[test]
+    fn rejects_relative_segments() {
+        assert!(!is_valid("."));
+        assert!(!is_valid("payments/../admin"));
+        assert!(!is_valid("payments/./db"));
+    }
+
+    #[test]
+    fn rejects_surrounding_spaces_and_control_characters() {
+        assert!(!is_valid(" payments/db"));
+        assert!(!is_valid("payments /db"));
+        assert!(!is_valid("payments/db\n"));
+        assert!(!is_valid("payments/d\u{0}b"));
+    }
+
+    #[test]
+    fn limits_the_length_in_bytes() {
+        assert!(is_valid(&"a".repeat(MAX_LENGTH)));
+        assert!(!is_valid(&"a".repeat(MAX_LENGTH + 1)));
+        assert!(!is_valid(&"é".repeat(MAX_LENGTH / 2 + 1)));
+    }
+
+    #[test]
+    fn normalizes_folders() {
+        assert_eq!(normalize_folder("/payments/prod/"), "payments/prod");
+        assert_eq!(normalize_folder("/"), "");
+        assert_eq!(folder_prefix("payments"), "payments/");
+        assert_eq!(folder_prefix(""), "");
+    }
+
+    #[test]
+    fn validates_patterns() {
+        assert!(is_valid_pattern("payments/prod/db/password"));
+        assert!(is_valid_pattern("payments/prod/*"));
+        assert!(!is_valid_pattern("/*"));
+        assert!(!is_valid_pattern("payments//*"));
+        assert!(!is_valid_pattern("payments/../*"));
+    }
+
+    #[test]
+    fn matches_exact_paths_and_folders() {
+        assert!(matches("payments/prod/db/password", "payments/prod/db/password"));
+        assert!(!matches("payments/prod/db/password", "payments/prod/db/password2"));
+        assert!(matches("payments/prod/*", "payments/prod/api_key"));
+        assert!(matches("payments/prod/*", "payments/prod/db/password"));
+        assert!(!matches("payments/prod/*", "payments/prod"));
+        assert!(!matches("payments/prod/*", "payments/production/api_key"));
+    }
+}
@@ -0,0 +1,168 @@
+use std::{fmt, fs, path::Path};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::pkcs8::DecodePrivateKey;
+use jsonwebtoken::{jwk::{AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType}, Algorithm, DecodingKey, EncodingKey};
+use rsa::{pkcs1::DecodeRsaPrivateKey, traits::PublicKeyParts, RsaPrivateKey};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct SigningKeyEntry {
+    kid: String,
+    alg: Algorithm,
+    private_key_path: String,
+    retired_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone)]
+pub struct SigningKey {
+    pub kid: Option<String>,
+    pub algorithm: Algorithm,
+    pub encoding_key: EncodingKey,
+    pub decoding_key: DecodingKey,
+    pub jwk: Option<Jwk>,
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+// The active key signs new tokens; retired keys only verify, and are dropped
+// once every token they could have signed has expired.
+#[derive(Clone)]
+pub struct SigningKeySet {
+    keys: Vec<SigningKey>,
+    token_maxage_minutes: i64,
+}
+
+impl fmt::Debug for SigningKeySet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.keys.iter().map(|key| (&key.kid, key.algorithm, key.retired_at)))
+            .finish()
+    }
+}
+
+impl SigningKeySet {
+    pub fn load(manifest_path: Option<&str>, jwt_secret: &str, token_maxage_minutes: i64) -> Self {
+        let keys = match manifest_path {
+            Some(manifest_path) => load_manifest(manifest_path),
+            None => vec![SigningKey {
+                kid: None,
+                algorithm: Algorithm::HS256,
+                encoding_key: EncodingKey::from_secret(jwt_secret.as_bytes()),
+                decoding_key: DecodingKey::from_secret(jwt_secret.as_bytes()),
+                jwk: None,
+                retired_at: None,
+            }],
+        };
+
+        let active_keys = keys.iter().filter(|key| key.retired_at.is_none()).count();
+        if active_keys != 1 {
+            panic!("JWT signing key manifest must contain exactly one key without `retired_at`, found {}", active_keys);
+        }
+
+        SigningKeySet { keys, token_maxage_minutes }
+    }
+
+    pub fn active(&self) -> &SigningKey {
+        self.keys
+            .iter()
+            .find(|key| key.retired_at.is_none())
+            .expect("signing key set has an active key")
+    }
+
+    fn is_usable(&self, key: &SigningKey) -> bool {
+        match key.retired_at {
+            Some(retired_at) => Utc::now() < retired_at + Duration::minutes(self.token_maxage_minutes),
+            None => true,
+        }
+    }
+
+    pub fn find(&self, kid: Option<&str>) -> Option<&SigningKey> {
+        self.keys
+            .iter()
+            .filter(|key| self.is_usable(key))
+            .find(|key| key.kid.as_deref() == kid)
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys
+                .iter()
+                .filter(|key| self.is_usable(key))
+                .filter_map(|key| key.jwk.clone())
+                .collect(),
+        }
+    }
+}
+
+fn load_manifest(manifest_path: &str) -> Vec<SigningKey> {
+    let manifest = fs::read_to_string(manifest_path)
+        .unwrap_or_else(|e| panic!("Failed to read JWT_SIGNING_KEYS_FILE: {}", e));
+
+    let entries: Vec<SigningKeyEntry> = serde_json::from_str(&manifest)
+        .unwrap_or_else(|e| panic!("Invalid JWT_SIGNING_KEYS_FILE: {}", e));
+
+    let base_dir = Path::new(manifest_path).parent().unwrap_or(Path::new("."));
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            let pem = fs::read_to_string(base_dir.join(&entry.private_key_path))
+                .unwrap_or_else(|e| panic!("Failed to read signing key `{}`: {}", entry.kid, e));
+
+            load_key(entry, &pem)
+        })
+        .collect()
+}
+
+fn load_key(entry: SigningKeyEntry, pem: &str) -> SigningKey {
+    let invalid = |e: &dyn fmt::Display| -> ! { panic!("Invalid signing key `{}`: {}", entry.kid, e) };
+
+    let (encoding_key, key_algorithm, parameters) = match entry.alg {
+        Algorithm::RS256 => {
+            let private_key = RsaPrivateKey::from_pkcs1_pem(pem)
+                .or_else(|_| rsa::pkcs8::DecodePrivateKey::from_pkcs8_pem(pem))
+                .unwrap_or_else(|e| invalid(&e));
+
+            let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(private_key.n().to_bytes_be()),
+                e: URL_SAFE_NO_PAD.encode(private_key.e().to_bytes_be()),
+            });
+
+            (EncodingKey::from_rsa_pem(pem.as_bytes()).unwrap_or_else(|e| invalid(&e)), KeyAlgorithm::RS256, parameters)
+        }
+        Algorithm::EdDSA => {
+            let private_key = ed25519_dalek::SigningKey::from_pkcs8_pem(pem)
+                .unwrap_or_else(|e| invalid(&e));
+
+            let parameters = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(private_key.verifying_key().to_bytes()),
+            });
+
+            (EncodingKey::from_ed_pem(pem.as_bytes()).unwrap_or_else(|e| invalid(&e)), KeyAlgorithm::EdDSA, parameters)
+        }
+        other => invalid(&format!("unsupported algorithm {:?}, use RS256 or EdDSA", other)),
+    };
+
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(entry.kid.clone()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    };
+
+    SigningKey {
+        decoding_key: DecodingKey::from_jwk(&jwk).unwrap_or_else(|e| invalid(&e)),
+        kid: Some(entry.kid.clone()),
+        algorithm: entry.alg,
+        encoding_key,
+        jwk: Some(jwk),
+        retired_at: entry.retired_at,
+    }
+}
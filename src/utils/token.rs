@@ -1,20 +1,31 @@
 use axum::http::StatusCode;
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{decode, decode_header, encode, Header, Validation};
 use serde::{Deserialize, Serialize};
 
-use crate::error::{ErrorMessage, HttpError};
+use crate::{error::{ErrorMessage, HttpError}, utils::signing_keys::SigningKeySet};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenClaims {
     pub sub: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approle: Option<String>,
     pub iat: usize,
     pub exp: usize,
 }
 
+// Scoped tokens are not tied to a login session and only grant read access
+// to secrets.
+pub const SECRETS_READ_SCOPE: &str = "secrets:read";
+
 pub fn create_token(
     user_id: &str,
-    secret: &[u8],
+    session_id: &str,
+    signing_keys: &SigningKeySet,
     expires_in_seconds: i64,
 ) -> Result<String,  jsonwebtoken::errors::Error> {
     if user_id.is_empty() {
@@ -24,31 +35,76 @@ pub fn create_token(
     let now = Utc::now();
     let iat = now.timestamp() as usize;
     let exp = (now + Duration::minutes(expires_in_seconds)).timestamp() as usize;
-    let claims: TokenClaims = TokenClaims { 
-        sub: user_id.to_string(), 
-        iat, 
-        exp, 
+    let claims: TokenClaims = TokenClaims {
+        sub: user_id.to_string(),
+        sid: Some(session_id.to_string()),
+        scope: None,
+        approle: None,
+        iat,
+        exp,
     };
 
+    sign(&claims, signing_keys)
+}
+
+pub fn create_scoped_token(
+    user_id: &str,
+    scope: &str,
+    approle_id: Option<&str>,
+    signing_keys: &SigningKeySet,
+    expires_in_minutes: i64,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    if user_id.is_empty() {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidSubject.into());
+    }
+
+    let now = Utc::now();
+    let claims = TokenClaims {
+        sub: user_id.to_string(),
+        sid: None,
+        scope: Some(scope.to_string()),
+        approle: approle_id.map(|approle_id| approle_id.to_string()),
+        iat: now.timestamp() as usize,
+        exp: (now + Duration::minutes(expires_in_minutes)).timestamp() as usize,
+    };
+
+    sign(&claims, signing_keys)
+}
+
+fn sign(claims: &TokenClaims, signing_keys: &SigningKeySet) -> Result<String, jsonwebtoken::errors::Error> {
+    let signing_key = signing_keys.active();
+
+    let mut header = Header::new(signing_key.algorithm);
+    header.kid = signing_key.kid.clone();
+
     encode(
-        &Header::default(), 
-        &claims, 
-        &EncodingKey::from_secret(secret),
+        &header,
+        claims,
+        &signing_key.encoding_key,
     )
 }
 
 pub fn decode_token<T: Into<String>>(
     token: T,
-    secret: &[u8],
-) -> Result<String, HttpError> {
+    signing_keys: &SigningKeySet,
+) -> Result<TokenClaims, HttpError> {
+    let token = token.into();
+    let invalid_token = || HttpError::new(ErrorMessage::InvalidToken.to_string(), StatusCode::UNAUTHORIZED);
+
+    let header = decode_header(&token).map_err(|_| invalid_token())?;
+
+    let signing_key = signing_keys
+        .find(header.kid.as_deref())
+        .ok_or_else(invalid_token)?;
+
     let decoded = decode::<TokenClaims>(
-        &token.into(),
-        &DecodingKey::from_secret(secret),
-        &Validation::new(Algorithm::HS256),
+        &token,
+        &signing_key.decoding_key,
+        &Validation::new(signing_key.algorithm),
     );
 
     match decoded {
-        Ok(token) => Ok(token.claims.sub),
-        Err(_) => Err(HttpError::new(ErrorMessage::InvalidToken.to_string(), StatusCode::UNAUTHORIZED)),
+        Ok(token) => Ok(token.claims),
+        Err(_) => Err(invalid_token()),
     }
-}
\ No newline at end of file
+}
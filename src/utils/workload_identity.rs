@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{config::WorkloadIssuer, error::HttpError, utils::oidc};
+
+#[derive(Debug, Deserialize)]
+struct DiscoveryDocument {
+    jwks_uri: String,
+}
+
+// CI and Kubernetes issuers only publish `jwks_uri` in their discovery
+// document, so the full `oidc::discover` metadata cannot be used here.
+pub async fn jwks_uri(issuer: &WorkloadIssuer) -> Result<String, HttpError> {
+    if let Some(jwks_url) = &issuer.jwks_url {
+        return Ok(jwks_url.clone());
+    }
+
+    let url = format!("{}/.well-known/openid-configuration", issuer.issuer.trim_end_matches('/'));
+
+    let document = reqwest::get(&url)
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| HttpError::server_error(format!("OIDC discovery failed: {}", e)))?
+        .json::<DiscoveryDocument>()
+        .await
+        .map_err(|e| HttpError::server_error(format!("Invalid OIDC discovery document: {}", e)))?;
+
+    Ok(document.jwks_uri)
+}
+
+pub async fn verify(issuer: &WorkloadIssuer, token: &str, audience: &str) -> Result<Value, HttpError> {
+    let jwks = oidc::fetch_jwks(&jwks_uri(issuer).await?).await?;
+
+    oidc::verify_jwt::<Value>(token, &jwks, &issuer.issuer, audience)
+        .ok_or_else(|| HttpError::unauthorized("JWT is invalid or expired"))
+}
+
+// Every bound claim must match one of its allowed values. Claim names starting
+// with `/` are JSON pointers into nested claims, and `*` in a value matches any
+// sequence of characters.
+pub fn matches_bound_claims(bound_claims: &HashMap<String, Vec<String>>, claims: &Value) -> bool {
+    !bound_claims.is_empty() && bound_claims.iter().all(|(name, allowed)| {
+        let claim = if name.starts_with('/') {
+            claims.pointer(name)
+        } else {
+            claims.get(name)
+        };
+
+        let values = match claim {
+            Some(Value::Array(values)) => values.iter().filter_map(claim_value).collect(),
+            Some(value) => claim_value(value).into_iter().collect(),
+            None => Vec::new(),
+        };
+
+        values.iter().any(|value| allowed.iter().any(|pattern| glob_match(pattern, value)))
+    })
+}
+
+fn claim_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn bound(claims: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        claims
+            .iter()
+            .map(|(name, values)| (name.to_string(), values.iter().map(|value| value.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn glob_matches_literals_exactly() {
+        assert!(glob_match("main", "main"));
+        assert!(!glob_match("main", "main2"));
+        assert!(!glob_match("main", "mai"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "x"));
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("repo:acme/*", "repo:acme/api"));
+        assert!(!glob_match("repo:acme/*", "repo:other/api"));
+        assert!(glob_match("*:ref:refs/heads/main", "repo:acme/api:ref:refs/heads/main"));
+        assert!(glob_match("repo:*/api:*", "repo:acme/api:ref:refs/tags/v1"));
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(glob_match("a**c", "ac"));
+    }
+
+    #[test]
+    fn glob_does_not_reuse_characters_between_parts() {
+        assert!(!glob_match("ab*ba", "aba"));
+        assert!(!glob_match("a*b*c", "acb"));
+        assert!(glob_match("ab*ba", "abba"));
+    }
+
+    #[test]
+    fn matches_every_bound_claim() {
+        let claims = json!({ "repository": "acme/api", "ref": "refs/heads/main" });
+
+        assert!(matches_bound_claims(&bound(&[("repository", &["acme/*"]), ("ref", &["refs/heads/main"])]), &claims));
+        assert!(!matches_bound_claims(&bound(&[("repository", &["acme/*"]), ("ref", &["refs/tags/*"])]), &claims));
+        assert!(matches_bound_claims(&bound(&[("ref", &["refs/tags/*", "refs/heads/*"])]), &claims));
+    }
+
+    #[test]
+    fn refuses_missing_claims_and_empty_bindings() {
+        let claims = json!({ "repository": "acme/api" });
+
+        assert!(!matches_bound_claims(&bound(&[("environment", &["*"])]), &claims));
+        assert!(!matches_bound_claims(&HashMap::new(), &claims));
+    }
+
+    #[test]
+    fn matches_nested_array_and_scalar_claims() {
+        let claims = json!({
+            "kubernetes.io": { "namespace": "billing", "serviceaccount": { "name": "worker" } },
+            "groups": ["dev", "ops"],
+            "run_attempt": 2,
+            "protected": true,
+            "object": { "nested": "value" },
+        });
+
+        assert!(matches_bound_claims(&bound(&[("/kubernetes.io/serviceaccount/name", &["worker"])]), &claims));
+        assert!(!matches_bound_claims(&bound(&[("/kubernetes.io/namespace", &["default"])]), &claims));
+        assert!(matches_bound_claims(&bound(&[("groups", &["ops"])]), &claims));
+        assert!(matches_bound_claims(&bound(&[("run_attempt", &["2"])]), &claims));
+        assert!(matches_bound_claims(&bound(&[("protected", &["true"])]), &claims));
+        assert!(!matches_bound_claims(&bound(&[("object", &["*"])]), &claims));
+    }
+}